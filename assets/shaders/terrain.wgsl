@@ -0,0 +1,153 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+
+#import bevy_pbr::mesh_functions
+#import bevy_pbr::pbr_types
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::pbr_ambient
+#import bevy_pbr::shadows
+#import bevy_pbr::fog
+#import bevy_pbr::pbr_functions
+
+struct TerrainMaterial {
+    texture_scale: f32,
+    sky_light: f32,
+    fade_start: f32,
+    fade_duration: f32,
+    opacity: f32,
+    alpha_cutoff: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> material: TerrainMaterial;
+@group(1) @binding(1)
+var terrain_texture: texture_2d_array<f32>;
+@group(1) @binding(2)
+var terrain_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) block_weights: vec4<f32>,
+    @location(3) ambient_occlusion: f32,
+    @location(4) light: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) block_weights: vec4<f32>,
+    @location(3) ambient_occlusion: f32,
+    @location(4) light: vec2<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.clip_position = mesh_position_world_to_clip(out.world_position);
+    out.world_normal = mesh_normal_local_to_world(vertex.normal);
+    out.block_weights = vertex.block_weights;
+    out.ambient_occlusion = vertex.ambient_occlusion;
+    out.light = vertex.light;
+
+    return out;
+}
+
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) block_weights: vec4<f32>,
+    @location(3) ambient_occlusion: f32,
+    @location(4) light: vec2<f32>,
+};
+
+// Terrain has no UVs, so each layer is projected along the three world axes
+// and blended by how much the surface faces each of them.
+fn triplanar(position: vec3<f32>, blend: vec3<f32>, layer: i32) -> vec4<f32> {
+    let x = textureSample(terrain_texture, terrain_sampler, position.zy, layer);
+    let y = textureSample(terrain_texture, terrain_sampler, position.xz, layer);
+    let z = textureSample(terrain_texture, terrain_sampler, position.xy, layer);
+
+    return x * blend.x + y * blend.y + z * blend.z;
+}
+
+// Threshold from a 4x4 ordered dither pattern, so fading chunks can stay
+// opaque and discard a growing share of their pixels instead of blending.
+fn dither_threshold(frag_coord: vec2<f32>) -> f32 {
+    let x = u32(frag_coord.x) % 4u;
+    let y = u32(frag_coord.y) % 4u;
+    var bayer = array<u32, 16>(0u, 8u, 2u, 10u, 12u, 4u, 14u, 6u, 3u, 11u, 1u, 9u, 15u, 7u, 13u, 5u);
+
+    return (f32(bayer[y * 4u + x]) + 0.5) / 16.0;
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    if (material.fade_duration > 0.0) {
+        let fade = (globals.time - material.fade_start) / material.fade_duration;
+        // See `TerrainMaterial::fade_start` for wrapping.
+        if (fade >= 0.0 && fade < dither_threshold(in.frag_coord.xy)) {
+            discard;
+        }
+    }
+
+    let normal = normalize(in.world_normal);
+    var blend = pow(abs(normal), vec3<f32>(4.0));
+    blend = blend / (blend.x + blend.y + blend.z);
+
+    let position = in.world_position.xyz * material.texture_scale;
+    var base_color = triplanar(position, blend, 0) * in.block_weights.x
+        + triplanar(position, blend, 1) * in.block_weights.y
+        + triplanar(position, blend, 2) * in.block_weights.z
+        + triplanar(position, blend, 3) * in.block_weights.w;
+
+    // Ambient occlusion darkens the albedo so it also affects direct light,
+    // which makes the shape of the terrain easier to read.
+    base_color = vec4<f32>(base_color.rgb * in.ambient_occlusion, base_color.a * material.opacity);
+
+    // Voxel light: places the sky can't reach are lit by blocks alone, whose
+    // light also glows on its own so it shows up in the dark.
+    let sky_light = in.light.x * material.sky_light;
+    let block_light = in.light.y;
+    let emissive = base_color.rgb * vec3<f32>(1.0, 0.8, 0.5) * block_light * block_light;
+    base_color = vec4<f32>(base_color.rgb * max(max(sky_light, block_light), 0.05), base_color.a);
+
+    var pbr_input: PbrInput = pbr_input_new();
+    pbr_input.material.base_color = base_color;
+    pbr_input.material.emissive = vec4<f32>(emissive, 1.0);
+    // Translucent surfaces keep their alpha and alpha-tested ones are cut out
+    // below the cutoff, matching the material's alpha mode.
+    if (material.opacity < 1.0) {
+        pbr_input.material.flags = STANDARD_MATERIAL_FLAGS_ALPHA_MODE_BLEND;
+    } else if (material.alpha_cutoff > 0.0) {
+        pbr_input.material.flags = STANDARD_MATERIAL_FLAGS_ALPHA_MODE_MASK;
+        pbr_input.material.alpha_cutoff = material.alpha_cutoff;
+    }
+    pbr_input.material.perceptual_roughness = 0.9;
+    pbr_input.frag_coord = in.frag_coord;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = prepare_world_normal(normal, false, in.is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = pbr_input.world_normal;
+    pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
+    pbr_input.flags = mesh.flags;
+
+    var output_color = pbr(pbr_input);
+
+    if (fog.mode != FOG_MODE_OFF) {
+        output_color = apply_fog(output_color, in.world_position.xyz, view.world_position.xyz);
+    }
+
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color);
+#endif
+
+    return output_color;
+}
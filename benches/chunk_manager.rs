@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use voxel_engine::chunk::{Chunk, CHUNK_SIZE, MAX_LOD};
+use voxel_engine::chunk_manager::ChunkManager;
+
+const RADIUS: i32 = 2;
+
+// A square of chunks around the origin, all at the same level of detail.
+fn chunk_manager(lod: u32) -> ChunkManager {
+    let mut chunk_manager = ChunkManager::default();
+
+    for x in -RADIUS..=RADIUS {
+        for z in -RADIUS..=RADIUS {
+            chunk_manager.insert_chunk(Chunk::new(IVec3::new(x, 0, z) * CHUNK_SIZE, lod));
+        }
+    }
+
+    chunk_manager
+}
+
+// Looks up every voxel position in the loaded area, so coarser chunks answer
+// the same number of lookups from fewer voxels.
+fn voxel_lookups(c: &mut Criterion) {
+    let min = IVec3::new(-RADIUS, 0, -RADIUS) * CHUNK_SIZE;
+    let max = IVec3::new(RADIUS + 1, 1, RADIUS + 1) * CHUNK_SIZE;
+    let mut group = c.benchmark_group("get_voxel_at_global_position");
+
+    for lod in 0..=MAX_LOD {
+        let chunk_manager = chunk_manager(lod);
+        let size = CHUNK_SIZE >> lod;
+
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                let mut active = 0;
+                for x in min.x..max.x {
+                    for y in min.y..max.y {
+                        for z in min.z..max.z {
+                            let voxel = chunk_manager
+                                .get_voxel_at_global_position(black_box(IVec3::new(x, y, z)));
+                            if voxel.is_some_and(|voxel| voxel.is_active) {
+                                active += 1;
+                            }
+                        }
+                    }
+                }
+                active
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, voxel_lookups);
+criterion_main!(benches);
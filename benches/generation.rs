@@ -0,0 +1,26 @@
+use bevy::prelude::*;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use voxel_engine::chunk::{CHUNK_SIZE, MAX_LOD};
+use voxel_engine::voxel::VoxelData;
+
+// `CHUNK_SIZE` is fixed at compile time, so chunk sizes are varied through
+// the level of detail, which halves the voxels along each axis per level.
+fn height_map(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_height_map");
+
+    for lod in 0..=MAX_LOD {
+        let size = CHUNK_SIZE >> lod;
+        group.bench_with_input(BenchmarkId::from_parameter(size), &lod, |b, &lod| {
+            b.iter(|| VoxelData::generate_height_map(black_box(IVec3::ZERO), lod))
+        });
+    }
+
+    group.finish();
+}
+
+fn sphere(c: &mut Criterion) {
+    c.bench_function("generate_sphere", |b| b.iter(VoxelData::generate_sphere));
+}
+
+criterion_group!(benches, height_map, sphere);
+criterion_main!(benches);
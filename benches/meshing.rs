@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use voxel_engine::chunk::{Chunk, CHUNK_SIZE, MAX_LOD};
+use voxel_engine::chunk_manager::ChunkManager;
+use voxel_engine::mesh::{ChunkMeshInput, MeshData, MeshSettings, Mesher, Shading};
+use voxel_engine::voxel::RenderLayer;
+
+// Mesh input for a chunk with its surrounding chunks loaded, so border cells
+// are meshed the same way they are in game. `CHUNK_SIZE` is fixed at compile
+// time, so chunk sizes are varied through the level of detail instead.
+fn chunk_mesh_input(lod: u32) -> ChunkMeshInput {
+    let mut chunk_manager = ChunkManager::default();
+
+    for x in -1..=1 {
+        for z in -1..=1 {
+            chunk_manager.insert_chunk(Chunk::new(IVec3::new(x, 0, z) * CHUNK_SIZE, lod));
+        }
+    }
+
+    ChunkMeshInput::new(IVec3::ZERO, &chunk_manager).unwrap()
+}
+
+fn compare_meshers(c: &mut Criterion) {
+    let input = chunk_mesh_input(0);
+    let mut group = c.benchmark_group("mesher");
+
+    for (name, mesher) in [
+        ("marching_cubes", Mesher::MarchingCubes),
+        ("surface_nets", Mesher::SurfaceNets),
+    ] {
+        let mesh_settings = MeshSettings {
+            mesher,
+            ..default()
+        };
+        // The vertex count is part of the id, so the meshers' output sizes
+        // are compared along with their times.
+        let mesh_data = MeshData::generate(mesh_settings, &input, RenderLayer::Opaque);
+        let id = BenchmarkId::new(name, format!("{} vertices", mesh_data.vertices.len()));
+
+        group.bench_function(id, |b| {
+            b.iter(|| MeshData::generate(mesh_settings, &input, RenderLayer::Opaque))
+        });
+    }
+
+    group.finish();
+}
+
+fn marching_cubes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_marching_cubes");
+
+    for lod in 0..=MAX_LOD {
+        let input = chunk_mesh_input(lod);
+        group.bench_function(BenchmarkId::from_parameter(input.size()), |b| {
+            b.iter(|| MeshData::generate_marching_cubes(&input, RenderLayer::Opaque))
+        });
+    }
+
+    group.finish();
+}
+
+fn create_mesh(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_mesh");
+
+    for lod in 0..=MAX_LOD {
+        let input = chunk_mesh_input(lod);
+        let mesh_data = MeshData::generate(MeshSettings::default(), &input, RenderLayer::Opaque);
+
+        for (name, shading) in [("smooth", Shading::Smooth), ("flat", Shading::Flat)] {
+            group.bench_function(BenchmarkId::new(name, input.size()), |b| {
+                b.iter(|| mesh_data.create_mesh(shading))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, compare_meshers, marching_cubes, create_mesh);
+criterion_main!(benches);
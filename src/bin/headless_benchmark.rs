@@ -0,0 +1,206 @@
+// Streams chunks without a window or GPU while a chunk loader follows a
+// scripted path, then reports throughput, peak memory and frame times. The
+// terrain generator's seed is fixed, so every run loads the same chunks.
+//
+//     cargo run --release --bin headless_benchmark -- [--frames N] [--radius R]
+
+use bevy::prelude::*;
+use bevy::utils::{Duration, Instant};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use voxel_engine::chunk::CHUNK_SIZE;
+use voxel_engine::chunk_manager::events::{ChunkGenerated, ChunkMeshed};
+use voxel_engine::chunk_manager::plugin::{ChunkLoader, ChunkStreamingPlugin};
+use voxel_engine::material::{TerrainMaterial, TerrainMaterials};
+
+const DEFAULT_FRAMES: usize = 1200;
+// Loader speed in voxels per frame, independent of how long frames take so
+// the path is the same on every machine.
+const SPEED: f32 = 0.5;
+// Corners of the path in chunks, walked in order and back to the start.
+const WAYPOINTS: [Vec2; 4] = [
+    Vec2::new(0.0, 0.0),
+    Vec2::new(24.0, 0.0),
+    Vec2::new(24.0, 24.0),
+    Vec2::new(0.0, 24.0),
+];
+
+#[global_allocator]
+static ALLOCATOR: PeakAllocator = PeakAllocator::new();
+
+// Tracks the peak number of bytes allocated on the heap.
+struct PeakAllocator {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl PeakAllocator {
+    const fn new() -> Self {
+        Self {
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = self.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            self.peak.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size > layout.size() {
+                let grown = new_size - layout.size();
+                let current = self.current.fetch_add(grown, Ordering::Relaxed) + grown;
+                self.peak.fetch_max(current, Ordering::Relaxed);
+            } else {
+                self.current
+                    .fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+            }
+        }
+        new_ptr
+    }
+}
+
+#[derive(Resource, Default)]
+struct ChunkCounts {
+    generated: usize,
+    meshed: usize,
+}
+
+fn count_chunks(
+    mut counts: ResMut<ChunkCounts>,
+    mut chunk_generated: EventReader<ChunkGenerated>,
+    mut chunk_meshed: EventReader<ChunkMeshed>,
+) {
+    counts.generated += chunk_generated.iter().count();
+    counts.meshed += chunk_meshed.iter().count();
+}
+
+// Position along the closed path after travelling `distance` voxels.
+fn path_position(distance: f32) -> Vec3 {
+    let segments = WAYPOINTS
+        .iter()
+        .zip(WAYPOINTS.iter().cycle().skip(1))
+        .map(|(start, end)| (*start * CHUNK_SIZE as f32, *end * CHUNK_SIZE as f32));
+    let length = segments
+        .clone()
+        .map(|(start, end)| start.distance(end))
+        .sum::<f32>();
+
+    let mut distance = distance % length;
+    for (start, end) in segments {
+        let segment_length = start.distance(end);
+        if distance <= segment_length {
+            let position = start.lerp(end, distance / segment_length);
+            return Vec3::new(position.x, 0.0, position.y);
+        }
+        distance -= segment_length;
+    }
+
+    Vec3::ZERO
+}
+
+fn percentile(sorted: &[Duration], percentile: f64) -> Duration {
+    let index = ((sorted.len() as f64 * percentile).ceil() as usize).clamp(1, sorted.len());
+    sorted[index - 1]
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn main() {
+    let mut frames = DEFAULT_FRAMES;
+    let mut radius = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().and_then(|value| value.parse().ok());
+        match (arg.as_str(), value) {
+            ("--frames", Some(value)) => frames = value,
+            ("--radius", Some(value)) => radius = Some(value as i32),
+            _ => {
+                eprintln!("usage: headless_benchmark [--frames N] [--radius R]");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(TransformPlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<TerrainMaterial>()
+        .insert_resource(TerrainMaterials::new(TerrainMaterial::new(
+            Handle::default(),
+        )))
+        .add_plugin(ChunkStreamingPlugin)
+        .init_resource::<ChunkCounts>()
+        .add_system(count_chunks);
+
+    let loader = app
+        .world
+        .spawn((TransformBundle::default(), ChunkLoader { radius }))
+        .id();
+
+    let mut frame_times = Vec::with_capacity(frames);
+    let start = Instant::now();
+
+    for frame in 0..frames {
+        let mut transform = app.world.get_mut::<Transform>(loader).unwrap();
+        transform.translation = path_position(frame as f32 * SPEED);
+
+        let frame_start = Instant::now();
+        app.update();
+        frame_times.push(frame_start.elapsed());
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let counts = app.world.resource::<ChunkCounts>();
+    frame_times.sort();
+
+    println!("frames: {frames}");
+    println!("elapsed: {elapsed:.2} s");
+    println!(
+        "chunks generated: {} ({:.1}/s)",
+        counts.generated,
+        counts.generated as f64 / elapsed
+    );
+    println!(
+        "chunks meshed: {} ({:.1}/s)",
+        counts.meshed,
+        counts.meshed as f64 / elapsed
+    );
+    println!(
+        "peak heap memory: {:.1} MiB",
+        ALLOCATOR.peak() as f64 / (1024.0 * 1024.0)
+    );
+
+    if !frame_times.is_empty() {
+        println!(
+            "frame time: p50 {:.2} ms, p90 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+            milliseconds(percentile(&frame_times, 0.5)),
+            milliseconds(percentile(&frame_times, 0.9)),
+            milliseconds(percentile(&frame_times, 0.99)),
+            milliseconds(frame_times[frame_times.len() - 1]),
+        );
+    }
+}
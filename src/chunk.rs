@@ -1,21 +1,39 @@
 use crate::voxel::VoxelData;
+use bevy::log::info_span;
 use bevy::prelude::*;
 
 pub const CHUNK_SIZE: i32 = 8;
 
+// Coarsest level of detail, where a chunk is a single voxel wide.
+pub const MAX_LOD: u32 = CHUNK_SIZE.trailing_zeros();
+
 #[derive(Debug, Clone)]
 pub struct Chunk {
     pub voxel_data: VoxelData,
     pub position: IVec3,
+    // Each level of detail halves the number of voxels along every axis.
+    pub lod: u32,
 }
 
 impl Chunk {
-    pub fn new(position: IVec3) -> Self {
-        let voxel_data = VoxelData::generate_height_map(position);
+    pub fn new(position: IVec3, lod: u32) -> Self {
+        let _span = info_span!("generate_chunk", ?position, lod).entered();
+        let voxel_data = VoxelData::generate_height_map(position, lod);
 
         Self {
             voxel_data,
             position,
+            lod,
         }
     }
+
+    /// Number of voxels along each axis.
+    pub fn size(&self) -> i32 {
+        CHUNK_SIZE >> self.lod
+    }
+
+    /// Width of a voxel in world units.
+    pub fn scale(&self) -> i32 {
+        1 << self.lod
+    }
 }
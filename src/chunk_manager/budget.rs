@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+use bevy::utils::{Duration, Instant};
+
+/// Limits on how much of each stage of the chunk pipeline runs per frame.
+/// Larger budgets load terrain faster, smaller ones keep frame times stable.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct ChunkBudget {
+    /// Generated chunks to add and chunks to start generating.
+    pub generating: StageBudget,
+    /// Chunks to start meshing.
+    pub meshing: StageBudget,
+    /// Finished meshes to spawn.
+    pub spawning: StageBudget,
+    /// Chunks to despawn.
+    pub despawning: StageBudget,
+}
+
+impl Default for ChunkBudget {
+    fn default() -> Self {
+        Self {
+            generating: StageBudget::new(32, Duration::from_millis(3)),
+            meshing: StageBudget::new(16, Duration::from_millis(3)),
+            spawning: StageBudget::new(16, Duration::from_millis(2)),
+            despawning: StageBudget::new(64, Duration::from_millis(1)),
+        }
+    }
+}
+
+/// Work allowed for one stage per frame. Either limit is ignored when `None`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageBudget {
+    pub max_chunks: Option<usize>,
+    pub max_time: Option<Duration>,
+}
+
+impl StageBudget {
+    pub fn new(max_chunks: usize, max_time: Duration) -> Self {
+        Self {
+            max_chunks: Some(max_chunks),
+            max_time: Some(max_time),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Starts tracking the work done by a stage this frame.
+    pub fn start(&self) -> BudgetTracker {
+        BudgetTracker {
+            budget: *self,
+            started: Instant::now(),
+            spent: 0,
+        }
+    }
+}
+
+pub struct BudgetTracker {
+    budget: StageBudget,
+    started: Instant,
+    spent: usize,
+}
+
+impl BudgetTracker {
+    pub fn is_exhausted(&self) -> bool {
+        self.budget
+            .max_chunks
+            .is_some_and(|max_chunks| self.spent >= max_chunks)
+            || self
+                .budget
+                .max_time
+                .is_some_and(|max_time| self.started.elapsed() >= max_time)
+    }
+
+    /// Records one chunk's worth of work.
+    pub fn spend(&mut self) {
+        self.spent += 1;
+    }
+}
@@ -0,0 +1,303 @@
+use crate::chunk::CHUNK_SIZE;
+use crate::mesh::ChunkMeshInput;
+use bevy::prelude::*;
+use bevy::render::primitives::{Aabb, Frustum};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::plugin::ChunkPosition;
+
+// Directions through the six faces of a chunk, in pairs of opposites.
+const FACES: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+fn opposite(face: usize) -> usize {
+    face ^ 1
+}
+
+/// Hides chunks the cameras can't see into, as they're only reachable
+/// through solid terrain. Meant for caves and terrain behind mountains.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct CaveCulling {
+    pub enabled: bool,
+}
+
+impl Default for CaveCulling {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Which pairs of a chunk's faces are connected through voxels that can be
+/// seen through, worked out when the chunk is meshed.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkConnectivity(u64);
+
+impl Default for ChunkConnectivity {
+    /// Every face is connected to every other, as in an empty chunk.
+    fn default() -> Self {
+        Self(u64::MAX)
+    }
+}
+
+impl ChunkConnectivity {
+    /// Flood fills each region of see-through voxels in the chunk and
+    /// connects all faces the region touches.
+    pub fn new(input: &ChunkMeshInput) -> Self {
+        let size = input.size();
+        let mut connectivity = Self(0);
+        let mut visited = HashSet::new();
+
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let start = IVec3::new(x, y, z);
+                    if input.is_opaque(start) || !visited.insert(start) {
+                        continue;
+                    }
+
+                    let mut faces = 0u8;
+                    let mut queue = VecDeque::from([start]);
+
+                    while let Some(pos) = queue.pop_front() {
+                        for (face, direction) in FACES.iter().enumerate() {
+                            let neighbour = pos + *direction;
+
+                            if neighbour.cmplt(IVec3::ZERO).any()
+                                || neighbour.cmpge(IVec3::splat(size)).any()
+                            {
+                                faces |= 1 << face;
+                            } else if !input.is_opaque(neighbour) && visited.insert(neighbour) {
+                                queue.push_back(neighbour);
+                            }
+                        }
+                    }
+
+                    connectivity.connect_all(faces);
+                }
+            }
+        }
+
+        connectivity
+    }
+
+    fn connect_all(&mut self, faces: u8) {
+        for a in 0..6 {
+            for b in 0..6 {
+                if faces & 1 << a != 0 && faces & 1 << b != 0 {
+                    self.0 |= 1 << (a * 6 + b);
+                }
+            }
+        }
+    }
+
+    /// Whether the chunk can be seen through from face `a` to face `b`,
+    /// indexed as in `FACES`.
+    pub fn is_connected(&self, a: usize, b: usize) -> bool {
+        self.0 & 1 << (a * 6 + b) != 0
+    }
+}
+
+/// Bounds of the mesh of the chunk at `chunk_position` with voxels `scale`
+/// units wide. Surface nets cells and skirts reach one voxel below the
+/// chunk.
+pub fn chunk_aabb(chunk_position: IVec3, scale: i32) -> Aabb {
+    Aabb::from_min_max(
+        (chunk_position - IVec3::splat(scale)).as_vec3(),
+        (chunk_position + CHUNK_SIZE).as_vec3(),
+    )
+}
+
+// Walks outwards from each camera's chunk through the faces of the chunks
+// that connect to the face it came in by, without ever turning back towards
+// the camera, and hides the chunks that weren't reached. Positions without a
+// chunk entity count as open space. The walk is bounded by the chunk
+// entities, with a layer of open space above and below.
+pub fn cull_chunks(
+    cave_culling: Res<CaveCulling>,
+    cameras: Query<(&Camera, &GlobalTransform, Option<&Frustum>), With<Camera3d>>,
+    mut chunks: Query<(&ChunkPosition, Option<&ChunkConnectivity>, &mut Visibility)>,
+) {
+    let cameras = cameras
+        .iter()
+        .filter(|(camera, ..)| camera.is_active)
+        .collect::<Vec<_>>();
+
+    if !cave_culling.enabled || cameras.is_empty() {
+        for (_, _, mut visibility) in &mut chunks {
+            set_if_neq(&mut visibility, Visibility::Inherited);
+        }
+        return;
+    }
+
+    let mut connectivity = HashMap::new();
+    let mut min = IVec3::splat(i32::MAX);
+    let mut max = IVec3::splat(i32::MIN);
+    for (chunk_position, chunk_connectivity, _) in &chunks {
+        connectivity.insert(
+            chunk_position.0,
+            chunk_connectivity.copied().unwrap_or_default(),
+        );
+        min = min.min(chunk_position.0);
+        max = max.max(chunk_position.0);
+    }
+
+    if connectivity.is_empty() {
+        return;
+    }
+
+    min -= IVec3::Y * CHUNK_SIZE;
+    max += IVec3::Y * CHUNK_SIZE;
+
+    let mut visible = HashSet::new();
+
+    for (_, transform, frustum) in cameras {
+        let camera_position = (transform.translation() / CHUNK_SIZE as f32)
+            .floor()
+            .as_ivec3();
+        let start = (camera_position * CHUNK_SIZE).clamp(min, max);
+
+        // Chunk positions with the face they were entered by and the
+        // directions taken to get there.
+        let mut queue = VecDeque::from([(start, None, 0u8)]);
+        let mut visited = HashSet::from([start]);
+
+        while let Some((chunk_position, entered_by, directions)) = queue.pop_front() {
+            visible.insert(chunk_position);
+            let chunk_connectivity = connectivity
+                .get(&chunk_position)
+                .copied()
+                .unwrap_or_default();
+
+            for (face, direction) in FACES.iter().enumerate() {
+                if directions & 1 << opposite(face) != 0 {
+                    continue;
+                }
+
+                if entered_by
+                    .is_some_and(|entered_by| !chunk_connectivity.is_connected(entered_by, face))
+                {
+                    continue;
+                }
+
+                let neighbour = chunk_position + *direction * CHUNK_SIZE;
+                if neighbour.cmplt(min).any() || neighbour.cmpgt(max).any() {
+                    continue;
+                }
+
+                // `Option::is_none_or` needs a newer Rust than Bevy 0.10 does.
+                #[allow(clippy::unnecessary_map_or)]
+                let in_view = frustum.map_or(true, |frustum| {
+                    let aabb =
+                        Aabb::from_min_max(neighbour.as_vec3(), (neighbour + CHUNK_SIZE).as_vec3());
+                    frustum.intersects_obb(&aabb, &Mat4::IDENTITY, true, false)
+                });
+
+                if in_view && visited.insert(neighbour) {
+                    queue.push_back((neighbour, Some(opposite(face)), directions | 1 << face));
+                }
+            }
+        }
+    }
+
+    for (chunk_position, _, mut visibility) in &mut chunks {
+        let new_visibility = if visible.contains(&chunk_position.0) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+
+        set_if_neq(&mut visibility, new_visibility);
+    }
+}
+
+fn set_if_neq(visibility: &mut Mut<Visibility>, new_visibility: Visibility) {
+    if **visibility != new_visibility {
+        **visibility = new_visibility;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_manager::ChunkManager;
+    use crate::voxel::{BlockType, Voxel, VoxelData};
+
+    const Y: usize = 2;
+    const NEG_Y: usize = 3;
+    const SIDES: [usize; 4] = [0, 1, 4, 5];
+
+    // Connectivity of a chunk, surrounded by identical chunks, whose voxels
+    // are solid where `is_solid` returns true.
+    fn connectivity(is_solid: impl Fn(IVec3) -> bool) -> ChunkConnectivity {
+        let mut chunk_manager = ChunkManager::default();
+
+        for x in -1..=1 {
+            for z in -1..=1 {
+                let voxels = (0..CHUNK_SIZE)
+                    .flat_map(|x| (0..CHUNK_SIZE).map(move |y| (x, y)))
+                    .flat_map(|(x, y)| (0..CHUNK_SIZE).map(move |z| IVec3::new(x, y, z)))
+                    .map(|pos| {
+                        let voxel = Voxel {
+                            is_active: is_solid(pos),
+                            block_type: BlockType::Rock,
+                            ..default()
+                        };
+                        (pos, voxel)
+                    })
+                    .collect();
+
+                chunk_manager.insert_chunk(Chunk {
+                    voxel_data: VoxelData { voxels },
+                    position: IVec3::new(x, 0, z) * CHUNK_SIZE,
+                    lod: 0,
+                });
+            }
+        }
+
+        ChunkConnectivity::new(&ChunkMeshInput::new(IVec3::ZERO, &chunk_manager).unwrap())
+    }
+
+    #[test]
+    fn solid_chunk_connects_no_faces() {
+        let connectivity = connectivity(|_| true);
+
+        for a in 0..FACES.len() {
+            for b in 0..FACES.len() {
+                assert!(!connectivity.is_connected(a, b), "{a} connects to {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn empty_chunk_connects_every_face() {
+        let connectivity = connectivity(|_| false);
+
+        for a in 0..FACES.len() {
+            for b in 0..FACES.len() {
+                assert!(
+                    connectivity.is_connected(a, b),
+                    "{a} doesn't connect to {b}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn wall_separates_top_from_bottom() {
+        let connectivity = connectivity(|pos| pos.y == CHUNK_SIZE / 2);
+
+        for side in SIDES {
+            assert!(connectivity.is_connected(Y, side));
+            assert!(connectivity.is_connected(NEG_Y, side));
+        }
+        assert!(!connectivity.is_connected(Y, NEG_Y));
+        assert!(!connectivity.is_connected(NEG_Y, Y));
+    }
+}
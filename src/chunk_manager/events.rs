@@ -0,0 +1,43 @@
+use crate::{chunk::Chunk, voxel::Voxel};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+/// A chunk has been generated and added to the chunk manager.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkGenerated {
+    pub position: IVec3,
+    /// Time spent generating the chunk on the async compute pool.
+    pub duration: Duration,
+}
+
+/// A chunk has been meshed, for the first time or again.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkMeshed {
+    pub position: IVec3,
+    /// Time spent meshing the chunk on the async compute pool.
+    pub duration: Duration,
+    /// Time spent on the main thread creating the chunk's meshes and
+    /// entities.
+    pub spawn_duration: Duration,
+}
+
+/// A chunk's entity has been spawned.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkSpawned {
+    pub position: IVec3,
+    pub entity: Entity,
+}
+
+/// A chunk has been unloaded and its entity despawned.
+#[derive(Debug, Clone)]
+pub struct ChunkUnloaded {
+    pub chunk: Chunk,
+}
+
+/// A voxel has been replaced through `ChunkManager::set_voxel`.
+#[derive(Debug, Clone)]
+pub struct VoxelChanged {
+    pub pos: IVec3,
+    pub old: Voxel,
+    pub new: Voxel,
+}
@@ -1,112 +1,754 @@
-use crate::{chunk::*, voxel::Voxel};
+use crate::{chunk::*, light, voxel::Voxel};
+use bevy::log::info_span;
 use bevy::prelude::*;
-use std::collections::{HashMap, VecDeque};
+use bevy::render::primitives::{Aabb, Frustum};
+use events::VoxelChanged;
+use std::collections::{HashMap, HashSet, VecDeque};
 
+// Chunks in view load as soon as chunks this many times closer that aren't.
+const FRUSTUM_PRIORITY_BOOST: i32 = 4;
+
+pub mod budget;
+pub mod culling;
+pub mod events;
 pub mod plugin;
+pub mod recorder;
+
+/// Stage of the loading pipeline a chunk position is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChunkState {
+    /// Waiting in the load queue to be generated.
+    Queued,
+    /// Being generated on the async compute pool.
+    Generating,
+    /// Generated and waiting to be meshed once its neighbours are loaded.
+    Generated,
+    /// Being meshed on the async compute pool.
+    Meshing,
+    /// Meshed and spawned.
+    Ready,
+    /// Out of range and waiting to be despawned.
+    Unloading,
+}
+
+/// Shape of the area around each chunk loader that chunks are loaded in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoadShape {
+    Square,
+    #[default]
+    Circle,
+    /// Like `Circle`, but shrinks as the loader moves away from the terrain
+    /// vertically.
+    Sphere,
+}
+
+impl LoadShape {
+    /// Whether a chunk `offset` chunks away from the loader lies within
+    /// `distance` chunks.
+    pub fn contains(&self, offset: IVec3, distance: i32) -> bool {
+        match self {
+            LoadShape::Square => offset.x.abs().max(offset.z.abs()) <= distance,
+            LoadShape::Circle => offset.x.pow(2) + offset.z.pow(2) <= distance.pow(2),
+            LoadShape::Sphere => offset.dot(offset) <= distance.pow(2),
+        }
+    }
+}
+
+/// Distances in chunks around chunk loaders that chunks are loaded and
+/// unloaded at. Changes take effect on the next update.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderDistance {
+    // Used by loaders without a radius of their own.
+    pub load: i32,
+    // Loaded chunks are kept until they're this far away, so moving back and
+    // forth across a chunk border doesn't load and unload the same chunks.
+    // Loaders with their own radius keep chunks as much further out.
+    pub unload: i32,
+    pub shape: LoadShape,
+}
+
+impl Default for RenderDistance {
+    fn default() -> Self {
+        Self {
+            load: 16,
+            unload: 18,
+            shape: LoadShape::default(),
+        }
+    }
+}
 
 #[derive(Resource, Debug)]
 pub struct ChunkManager {
     active_chunks: HashMap<IVec3, Chunk>,
-    load_queue: VecDeque<Chunk>,
+    // Every chunk position in the pipeline. A chunk that's queued to be
+    // generated again at another level of detail stays active meanwhile.
+    chunk_states: HashMap<IVec3, ChunkState>,
+    // Positions of chunks to generate, in the order they're loaded.
+    load_queue: VecDeque<IVec3>,
     unload_queue: VecDeque<Chunk>,
-    reload_queue: VecDeque<Chunk>,
-    render_distance: i32,
+    mesh_queue: VecDeque<IVec3>,
+    voxel_changes: Vec<VoxelChanged>,
+    // Areas around the chunk loaders as of the last update, which also set
+    // the level of detail chunks are generated at.
+    load_areas: Vec<LoadArea>,
+    loader_counts: HashMap<IVec3, u32>,
+    render_distance: RenderDistance,
+    // Width in chunks of each level of detail ring around the loaders.
+    lod_distance: i32,
 }
 
 impl Default for ChunkManager {
     fn default() -> Self {
         ChunkManager {
             active_chunks: HashMap::new(),
+            chunk_states: HashMap::new(),
             load_queue: VecDeque::new(),
             unload_queue: VecDeque::new(),
-            reload_queue: VecDeque::new(),
-            render_distance: 8,
+            mesh_queue: VecDeque::new(),
+            voxel_changes: Vec::new(),
+            load_areas: Vec::new(),
+            loader_counts: HashMap::new(),
+            render_distance: RenderDistance::default(),
+            lod_distance: 4,
         }
     }
 }
 
 impl ChunkManager {
+    /// Position of the chunk containing the voxel at `global_pos`.
+    pub fn chunk_position_of(global_pos: IVec3) -> IVec3 {
+        (global_pos.as_vec3() / CHUNK_SIZE as f32)
+            .floor()
+            .as_ivec3()
+            * CHUNK_SIZE
+    }
+
+    pub fn render_distance(&self) -> RenderDistance {
+        self.render_distance
+    }
+
+    pub fn set_render_distance(&mut self, render_distance: RenderDistance) {
+        self.render_distance = render_distance;
+    }
+
     pub fn get_voxel_at_global_position(&self, global_pos: IVec3) -> Option<&Voxel> {
-        let chunk_pos = (global_pos.as_vec3() / CHUNK_SIZE as f32).floor() * CHUNK_SIZE as f32;
-        let relative_voxel_pos = (global_pos % CHUNK_SIZE).abs();
+        let chunk_pos = Self::chunk_position_of(global_pos);
+        let relative_voxel_pos = global_pos - chunk_pos;
 
-        if let Some(chunk) = self.active_chunks.get(&chunk_pos.as_ivec3()) {
-            return chunk.voxel_data.voxels.get(&relative_voxel_pos);
+        // Coarse chunks return the closest voxel below the position.
+        if let Some(chunk) = self.active_chunks.get(&chunk_pos) {
+            return chunk
+                .voxel_data
+                .voxels
+                .get(&(relative_voxel_pos / chunk.scale()));
         }
 
         None
     }
 
-    // When loading a chunk, we need to add surrounding chunks to the
-    // reload_queue so that the mesh of those chunks can be updated to
-    // fit seamlessly to the newly loaded chunk.
-    pub fn load_chunk(&mut self) {
-        if let Some(chunk) = self.load_queue.pop_front() {
+    pub fn get_voxel_at_global_position_mut(&mut self, global_pos: IVec3) -> Option<&mut Voxel> {
+        let chunk_pos = Self::chunk_position_of(global_pos);
+        let relative_voxel_pos = global_pos - chunk_pos;
+        let chunk = self.active_chunks.get_mut(&chunk_pos)?;
+        let scale = chunk.scale();
+
+        chunk
+            .voxel_data
+            .voxels
+            .get_mut(&(relative_voxel_pos / scale))
+    }
+
+    /// Replaces the voxel at `global_pos`, updating the light around it and
+    /// meshing the affected chunks again. Returns the old voxel, or `None`
+    /// if the position isn't loaded.
+    pub fn set_voxel(&mut self, global_pos: IVec3, voxel: Voxel) -> Option<Voxel> {
+        let old = std::mem::replace(self.get_voxel_at_global_position_mut(global_pos)?, voxel);
+
+        let mut changed = light::update_voxel(self, global_pos, &old);
+        changed.insert(global_pos);
+        self.queue_remesh_around(&changed);
+
+        // Light has settled by now, so the new voxel is read back.
+        self.voxel_changes.push(VoxelChanged {
+            pos: global_pos,
+            old: old.clone(),
+            new: self.get_voxel_at_global_position(global_pos)?.clone(),
+        });
+
+        Some(old)
+    }
+
+    /// Removes and returns the voxel changes made since the last call.
+    pub fn take_voxel_changes(&mut self) -> Vec<VoxelChanged> {
+        std::mem::take(&mut self.voxel_changes)
+    }
+
+    pub fn chunk_state(&self, chunk_pos: IVec3) -> Option<ChunkState> {
+        self.chunk_states.get(&chunk_pos).copied()
+    }
+
+    /// Chunks that have been generated and not yet unloaded.
+    pub fn chunks(&self) -> impl ExactSizeIterator<Item = &Chunk> {
+        self.active_chunks.values()
+    }
+
+    /// Every chunk position in the pipeline, with its state.
+    pub fn chunk_states(&self) -> impl Iterator<Item = (IVec3, ChunkState)> + '_ {
+        self.chunk_states.iter().map(|(pos, state)| (*pos, *state))
+    }
+
+    /// Chunks waiting to be generated, in the order they'll load.
+    pub fn load_queue(&self) -> &VecDeque<IVec3> {
+        &self.load_queue
+    }
+
+    /// Chunks waiting to be meshed, for the first time or again.
+    pub fn mesh_queue(&self) -> &VecDeque<IVec3> {
+        &self.mesh_queue
+    }
+
+    /// Chunks out of range, waiting to be despawned.
+    pub fn unload_queue(&self) -> &VecDeque<Chunk> {
+        &self.unload_queue
+    }
+
+    /// Steps through the voxels along the ray from `origin` in `direction`
+    /// and returns the position of the first active one within
+    /// `max_distance`.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<IVec3> {
+        let direction = direction.try_normalize()?;
+        let mut pos = origin.floor().as_ivec3();
+
+        // Distance along the ray to the next voxel boundary on each axis, and
+        // between boundaries. Axes the ray runs parallel to are never crossed.
+        let mut next_boundary = Vec3::ZERO;
+        let mut boundary_distance = Vec3::ZERO;
+        let mut step = IVec3::ZERO;
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                next_boundary[axis] = f32::INFINITY;
+                boundary_distance[axis] = f32::INFINITY;
+                continue;
+            }
+
+            step[axis] = direction[axis].signum() as i32;
+            boundary_distance[axis] = 1.0 / direction[axis].abs();
+            let boundary = if step[axis] > 0 {
+                pos[axis] as f32 + 1.0
+            } else {
+                pos[axis] as f32
+            };
+            next_boundary[axis] = (boundary - origin[axis]) / direction[axis];
+        }
+
+        loop {
+            if self
+                .get_voxel_at_global_position(pos)
+                .is_some_and(|voxel| voxel.is_active)
+            {
+                return Some(pos);
+            }
+
+            let axis = if next_boundary.x < next_boundary.y && next_boundary.x < next_boundary.z {
+                0
+            } else if next_boundary.y < next_boundary.z {
+                1
+            } else {
+                2
+            };
+
+            if next_boundary[axis] > max_distance {
+                return None;
+            }
+
+            pos[axis] += step[axis];
+            next_boundary[axis] += boundary_distance[axis];
+        }
+    }
+
+    pub fn get_chunk(&self, chunk_pos: IVec3) -> Option<&Chunk> {
+        self.active_chunks.get(&chunk_pos)
+    }
+
+    pub fn get_chunk_mut(&mut self, chunk_pos: IVec3) -> Option<&mut Chunk> {
+        self.active_chunks.get_mut(&chunk_pos)
+    }
+
+    pub fn insert_chunk(&mut self, chunk: Chunk) -> Option<Chunk> {
+        self.active_chunks.insert(chunk.position, chunk)
+    }
+
+    /// Takes the next chunk to generate off the load queue, along with the
+    /// level of detail to generate it at.
+    pub fn start_generating(&mut self) -> Option<(IVec3, u32)> {
+        let position = self.load_queue.pop_front()?;
+        self.chunk_states.insert(position, ChunkState::Generating);
+
+        Some((position, self.lod_at(position)))
+    }
+
+    /// Adds a generated chunk and returns whether it was added. Chunks that
+    /// went out of range while being generated are dropped.
+    // A newly loaded chunk is queued for meshing, which waits until all of
+    // its neighbours are loaded so that the mesh fits seamlessly to them.
+    // When a chunk changes level of detail, its neighbours are meshed again
+    // so that they can add skirts along the new seam. Neighbours that light
+    // spills into are meshed again as well.
+    pub fn load_chunk(&mut self, chunk: Chunk) -> bool {
+        let (position, lod) = (chunk.position, chunk.lod);
+        if self.chunk_state(position) != Some(ChunkState::Generating) {
+            return false;
+        }
+
+        let _span = info_span!("load_chunk", ?position, lod).entered();
+
+        self.chunk_states.insert(position, ChunkState::Generated);
+        self.mesh_queue.push_back(position);
+
+        let old_chunk = self.active_chunks.insert(position, chunk);
+
+        let changed = light::light_chunk(self, position);
+        self.queue_remesh_around(&changed);
+
+        if old_chunk.is_some_and(|old_chunk| old_chunk.lod != lod) {
             for x in -1..=1 {
                 for z in -1..=1 {
-                    if x == 0 && z == 0 {
-                        continue;
+                    let neighbour_position = position + IVec3::new(x, 0, z) * CHUNK_SIZE;
+
+                    if (x != 0 || z != 0) && self.active_chunks.contains_key(&neighbour_position) {
+                        self.mesh_queue.push_back(neighbour_position);
                     }
+                }
+            }
+        }
 
-                    let chunk_x = chunk.position.x + CHUNK_SIZE * x;
-                    let chunk_z = chunk.position.z + CHUNK_SIZE * z;
+        true
+    }
 
-                    if let Some(other_chunk) =
-                        self.active_chunks.get(&IVec3::new(chunk_x, 0, chunk_z))
-                    {
-                        self.reload_queue.push_back(other_chunk.clone());
-                    }
+    // Meshes sample voxels up to two voxels past their chunk's border, so
+    // changes near a border are seen by the neighbouring chunks too.
+    fn queue_remesh_around(&mut self, positions: &HashSet<IVec3>) {
+        let mut chunk_positions = HashSet::new();
+
+        for pos in positions {
+            for x in [-2, 0, 2] {
+                for z in [-2, 0, 2] {
+                    chunk_positions.insert(Self::chunk_position_of(*pos + IVec3::new(x, 0, z)));
                 }
             }
+        }
 
-            self.active_chunks.insert(chunk.position, chunk);
+        for chunk_pos in chunk_positions {
+            if self.active_chunks.contains_key(&chunk_pos) && !self.mesh_queue.contains(&chunk_pos)
+            {
+                self.mesh_queue.push_back(chunk_pos);
+            }
         }
     }
 
-    pub fn unload_chunk(&mut self) {
-        if let Some(chunk) = self.unload_queue.pop_front() {
-            self.active_chunks.remove(&chunk.position);
+    // The world is a single chunk tall, so only horizontal neighbours are
+    // needed.
+    pub fn neighbours_loaded(&self, chunk_position: IVec3) -> bool {
+        (-1..=1).all(|x| {
+            (-1..=1).all(|z| {
+                let neighbour_position = chunk_position + IVec3::new(x, 0, z) * CHUNK_SIZE;
+                self.active_chunks.contains_key(&neighbour_position)
+            })
+        })
+    }
+
+    pub fn remesh_all(&mut self) {
+        self.mesh_queue.extend(self.active_chunks.keys());
+    }
+
+    /// Removes and returns the first queued chunk whose neighbours are
+    /// loaded. Chunks that were unloaded while waiting are dropped from the
+    /// queue.
+    pub fn take_meshable_chunk(&mut self) -> Option<IVec3> {
+        self.mesh_queue
+            .retain(|chunk_pos| self.active_chunks.contains_key(chunk_pos));
+
+        let index = self
+            .mesh_queue
+            .iter()
+            .position(|chunk_pos| self.neighbours_loaded(*chunk_pos))?;
+        let chunk_pos = self.mesh_queue.remove(index)?;
+
+        // A single mesh covers every time the chunk was queued.
+        self.mesh_queue.retain(|queued| *queued != chunk_pos);
+        self.set_state_unless_requeued(chunk_pos, ChunkState::Meshing);
+
+        Some(chunk_pos)
+    }
+
+    /// Marks a chunk whose mesh has been spawned as ready.
+    pub fn finish_meshing(&mut self, chunk_pos: IVec3) {
+        if self.chunk_state(chunk_pos) == Some(ChunkState::Meshing) {
+            self.chunk_states.insert(chunk_pos, ChunkState::Ready);
         }
     }
 
-    pub fn update(&mut self, player_chunk_position: IVec3) {
-        self.populate_load_queue(player_chunk_position);
-        self.populate_unload_queue(player_chunk_position);
+    // Chunks queued to be generated at another level of detail stay queued
+    // while their current mesh is updated.
+    fn set_state_unless_requeued(&mut self, chunk_pos: IVec3, state: ChunkState) {
+        let requeued = matches!(
+            self.chunk_state(chunk_pos),
+            Some(ChunkState::Queued | ChunkState::Generating)
+        );
+
+        if !requeued {
+            self.chunk_states.insert(chunk_pos, state);
+        }
     }
 
-    fn populate_load_queue(&mut self, player_chunk_position: IVec3) {
-        for x in -self.render_distance..=self.render_distance {
-            for z in -self.render_distance..=self.render_distance {
-                let mut chunk_pos = player_chunk_position + (IVec3::new(x, 0, z) * CHUNK_SIZE);
-                chunk_pos.y = 0;
+    pub fn unload_chunk(&mut self) -> Option<Chunk> {
+        let chunk = self.unload_queue.pop_front()?;
+        self.active_chunks.remove(&chunk.position);
+        self.chunk_states.remove(&chunk.position);
 
-                if self.active_chunks.contains_key(&chunk_pos) {
-                    continue;
-                }
+        Some(chunk)
+    }
+
+    /// Queues chunks to load and unload around the chunk loaders. Chunks
+    /// closest to a loader load first, with chunks inside a loader's frustum
+    /// taking precedence over ones of similar distance out of view.
+    pub fn update(&mut self, load_areas: Vec<LoadArea>) {
+        let _span = info_span!("update_load_queues").entered();
 
-                let chunk = Chunk::new(chunk_pos);
-                self.load_queue.push_back(chunk);
+        self.load_areas = load_areas;
+        self.count_loaders();
+        self.populate_load_queue();
+        self.populate_unload_queue();
+        self.prioritise_load_queue();
+    }
+
+    /// Number of chunk loaders keeping the chunk at `chunk_pos` loaded.
+    pub fn loader_count(&self, chunk_pos: IVec3) -> u32 {
+        self.loader_counts.get(&chunk_pos).copied().unwrap_or(0)
+    }
+
+    // Counts for every chunk the loaders whose unload distance reaches it,
+    // which keep it loaded.
+    fn count_loaders(&mut self) {
+        let RenderDistance { load, unload, .. } = self.render_distance;
+        self.loader_counts.clear();
+
+        for area in &self.load_areas {
+            let distance = area.radius + (unload - load).max(0);
+
+            for chunk_pos in self.area_chunks(area, distance) {
+                *self.loader_counts.entry(chunk_pos).or_default() += 1;
             }
         }
     }
 
-    fn populate_unload_queue(&mut self, player_chunk_position: IVec3) {
-        let mut keys_to_remove = Vec::new();
+    // Positions of the chunks within `distance` chunks of a load area's
+    // centre.
+    fn area_chunks(&self, area: &LoadArea, distance: i32) -> impl Iterator<Item = IVec3> {
+        let shape = self.render_distance.shape;
+        let center = area.chunk_position;
 
-        for chunk_pos in self.active_chunks.keys() {
-            if ((player_chunk_position.x / CHUNK_SIZE) - (chunk_pos.x / CHUNK_SIZE)).abs()
-                > self.render_distance
-                || ((player_chunk_position.z / CHUNK_SIZE) - (chunk_pos.z / CHUNK_SIZE)).abs()
-                    > self.render_distance
-            {
-                keys_to_remove.push(chunk_pos.clone());
+        (-distance..=distance).flat_map(move |x| {
+            (-distance..=distance).filter_map(move |z| {
+                let mut chunk_pos = center + IVec3::new(x, 0, z) * CHUNK_SIZE;
+                chunk_pos.y = 0;
+
+                shape
+                    .contains((chunk_pos - center) / CHUNK_SIZE, distance)
+                    .then_some(chunk_pos)
+            })
+        })
+    }
+
+    fn prioritise_load_queue(&mut self) {
+        let load_areas = &self.load_areas;
+
+        self.load_queue
+            .make_contiguous()
+            .sort_by_cached_key(|chunk_pos| {
+                load_areas
+                    .iter()
+                    .map(|area| area.load_priority(*chunk_pos))
+                    .min()
+                    .unwrap_or(i32::MAX)
+            });
+    }
+
+    fn populate_load_queue(&mut self) {
+        let mut chunk_positions = HashSet::new();
+        for area in &self.load_areas {
+            chunk_positions.extend(self.area_chunks(area, area.radius));
+        }
+
+        for chunk_pos in chunk_positions {
+            // Chunks already on their way are left alone. Ones that are
+            // loaded are generated again if their level of detail has
+            // changed.
+            match self.chunk_state(chunk_pos) {
+                Some(ChunkState::Queued | ChunkState::Generating | ChunkState::Unloading) => {
+                    continue
+                }
+                Some(_) if self.active_chunks[&chunk_pos].lod == self.lod_at(chunk_pos) => continue,
+                _ => {}
             }
+
+            self.chunk_states.insert(chunk_pos, ChunkState::Queued);
+            self.load_queue.push_back(chunk_pos);
         }
+    }
+
+    // Level of detail for a chunk, increasing by one every `lod_distance`
+    // chunks away from the nearest loader.
+    fn lod_at(&self, chunk_pos: IVec3) -> u32 {
+        let distance = self
+            .load_areas
+            .iter()
+            .map(|area| {
+                ((chunk_pos - area.chunk_position) / CHUNK_SIZE)
+                    .abs()
+                    .max_element()
+            })
+            .min()
+            .unwrap_or(i32::MAX);
+
+        ((distance / self.lod_distance) as u32).min(MAX_LOD)
+    }
+
+    // Loaded chunks are unloaded once no loader keeps them loaded. Chunks
+    // that haven't been loaded yet are forgotten straight away once they're
+    // past every loader's load distance.
+    fn populate_unload_queue(&mut self) {
+        let mut in_load_distance = HashSet::new();
+        for area in &self.load_areas {
+            in_load_distance.extend(self.area_chunks(area, area.radius));
+        }
+
+        let keys_to_remove: Vec<_> = self
+            .chunk_states
+            .keys()
+            .copied()
+            .filter(|chunk_pos| {
+                if self.active_chunks.contains_key(chunk_pos) {
+                    self.loader_count(*chunk_pos) == 0
+                } else {
+                    !in_load_distance.contains(chunk_pos)
+                }
+            })
+            .collect();
 
         for chunk_pos in keys_to_remove {
             if let Some(chunk) = self.active_chunks.remove(&chunk_pos) {
+                self.chunk_states.insert(chunk_pos, ChunkState::Unloading);
                 self.unload_queue.push_back(chunk);
+            } else if self.chunk_state(chunk_pos) != Some(ChunkState::Unloading) {
+                self.chunk_states.remove(&chunk_pos);
+            }
+        }
+
+        self.load_queue
+            .retain(|chunk_pos| self.chunk_states.get(chunk_pos) == Some(&ChunkState::Queued));
+    }
+}
+
+/// Area around a chunk loader that chunks are loaded in.
+#[derive(Debug, Clone)]
+pub struct LoadArea {
+    pub chunk_position: IVec3,
+    /// Load distance in chunks.
+    pub radius: i32,
+    pub frustum: Option<Frustum>,
+}
+
+impl LoadArea {
+    // Lower values load sooner. Priorities are squared distances, so chunks
+    // out of view are pushed back by the square of the boost.
+    fn load_priority(&self, chunk_pos: IVec3) -> i32 {
+        let offset = (chunk_pos - self.chunk_position) / CHUNK_SIZE;
+        let distance_squared = offset.x * offset.x + offset.z * offset.z;
+
+        let aabb = Aabb::from_min_max(chunk_pos.as_vec3(), (chunk_pos + CHUNK_SIZE).as_vec3());
+        let in_view = self
+            .frustum
+            .as_ref()
+            .is_some_and(|frustum| frustum.intersects_obb(&aabb, &Mat4::IDENTITY, true, false));
+
+        if in_view {
+            distance_squared
+        } else {
+            distance_squared * FRUSTUM_PRIORITY_BOOST * FRUSTUM_PRIORITY_BOOST
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_area(x: i32, radius: i32) -> LoadArea {
+        LoadArea {
+            chunk_position: IVec3::new(x, 0, 0) * CHUNK_SIZE,
+            radius,
+            frustum: None,
+        }
+    }
+
+    fn chunk_manager() -> ChunkManager {
+        let mut chunk_manager = ChunkManager::default();
+        chunk_manager.set_render_distance(RenderDistance {
+            load: 2,
+            unload: 4,
+            shape: LoadShape::Square,
+        });
+        chunk_manager
+    }
+
+    // Generates every queued chunk, as the streaming systems would.
+    fn load_queued(chunk_manager: &mut ChunkManager) {
+        while let Some((position, lod)) = chunk_manager.start_generating() {
+            chunk_manager.load_chunk(Chunk::new(position, lod));
+        }
+    }
+
+    #[test]
+    fn lod_steps_every_lod_distance_up_to_max_lod() {
+        let mut chunk_manager = chunk_manager();
+        chunk_manager.update(vec![load_area(0, 2)]);
+        assert_eq!(chunk_manager.lod_distance, 4);
+
+        // Distances in chunks with the level of detail chunks there get.
+        let rings = [
+            (0, 0),
+            (3, 0),
+            (4, 1),
+            (7, 1),
+            (8, 2),
+            (11, 2),
+            (12, 3),
+            (40, MAX_LOD),
+        ];
+        for (distance, lod) in rings {
+            for chunk_pos in [IVec3::new(distance, 0, 0), IVec3::new(-1, 0, -distance)] {
+                assert_eq!(
+                    chunk_manager.lod_at(chunk_pos * CHUNK_SIZE),
+                    lod,
+                    "at {distance}"
+                );
             }
         }
     }
+
+    #[test]
+    fn coarse_chunks_answer_lookups_from_their_own_grid() {
+        let mut chunk_manager = ChunkManager::default();
+        chunk_manager.insert_chunk(Chunk::new(IVec3::ZERO, 1));
+        let chunk = chunk_manager.get_chunk(IVec3::ZERO).unwrap();
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let pos = IVec3::new(x, y, z);
+                    let voxel = chunk_manager.get_voxel_at_global_position(pos).unwrap();
+                    let coarse_voxel = &chunk.voxel_data.voxels[&(pos / chunk.scale())];
+                    assert!(std::ptr::eq(voxel, coarse_voxel), "at {pos}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_in_view_load_before_nearer_chunks_out_of_view() {
+        let eye = Vec3::splat(CHUNK_SIZE as f32 / 2.0);
+        let view = Transform::from_translation(eye).looking_at(eye + Vec3::X, Vec3::Y);
+        let projection =
+            Mat4::perspective_infinite_reverse_rh(std::f32::consts::FRAC_PI_4, 1.0, 0.1);
+        let frustum =
+            Frustum::from_view_projection(&(projection * view.compute_matrix().inverse()));
+
+        let mut chunk_manager = chunk_manager();
+        chunk_manager.update(vec![LoadArea {
+            frustum: Some(frustum),
+            ..load_area(0, 3)
+        }]);
+
+        // Less than `FRUSTUM_PRIORITY_BOOST` times further away.
+        let in_view = IVec3::new(3, 0, 0) * CHUNK_SIZE;
+        let behind = IVec3::new(-1, 0, 0) * CHUNK_SIZE;
+        let rank = |chunk_pos| {
+            chunk_manager
+                .load_queue()
+                .iter()
+                .position(|queued| *queued == chunk_pos)
+                .unwrap()
+        };
+        assert!(rank(in_view) < rank(behind));
+    }
+
+    #[test]
+    fn positions_are_queued_once() {
+        let mut chunk_manager = chunk_manager();
+        chunk_manager.update(vec![load_area(0, 2)]);
+        let queued = chunk_manager.load_queue().len();
+        assert_eq!(queued, 25);
+
+        for _ in 0..3 {
+            chunk_manager.update(vec![load_area(0, 2), load_area(1, 2)]);
+        }
+
+        let unique = chunk_manager.load_queue().iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), chunk_manager.load_queue().len());
+        assert_eq!(unique.len(), 30);
+    }
+
+    #[test]
+    fn chunks_within_unload_distance_are_kept() {
+        let mut chunk_manager = chunk_manager();
+        chunk_manager.update(vec![load_area(0, 2)]);
+        load_queued(&mut chunk_manager);
+        assert!(chunk_manager.get_chunk(IVec3::ZERO).is_some());
+
+        // Past the load distance, but within the unload distance.
+        chunk_manager.update(vec![load_area(3, 2)]);
+        assert!(chunk_manager.get_chunk(IVec3::ZERO).is_some());
+        assert_ne!(
+            chunk_manager.chunk_state(IVec3::ZERO),
+            Some(ChunkState::Unloading)
+        );
+    }
+
+    #[test]
+    fn chunks_past_unload_distance_are_unloaded() {
+        let mut chunk_manager = chunk_manager();
+        chunk_manager.update(vec![load_area(0, 2)]);
+        load_queued(&mut chunk_manager);
+
+        chunk_manager.update(vec![load_area(5, 2)]);
+        assert_eq!(
+            chunk_manager.chunk_state(IVec3::ZERO),
+            Some(ChunkState::Unloading)
+        );
+        assert!(chunk_manager.get_chunk(IVec3::ZERO).is_none());
+        assert!(chunk_manager
+            .unload_queue()
+            .iter()
+            .any(|chunk| chunk.position == IVec3::ZERO));
+    }
+
+    #[test]
+    fn shared_chunks_stay_loaded_until_every_loader_leaves() {
+        let mut chunk_manager = chunk_manager();
+        chunk_manager.update(vec![load_area(0, 2), load_area(3, 2)]);
+        load_queued(&mut chunk_manager);
+        assert_eq!(chunk_manager.loader_count(IVec3::ZERO), 2);
+
+        chunk_manager.update(vec![load_area(-10, 2), load_area(3, 2)]);
+        assert_eq!(chunk_manager.loader_count(IVec3::ZERO), 1);
+        assert!(chunk_manager.get_chunk(IVec3::ZERO).is_some());
+
+        chunk_manager.update(vec![load_area(-10, 2), load_area(10, 2)]);
+        assert_eq!(chunk_manager.loader_count(IVec3::ZERO), 0);
+        assert_eq!(
+            chunk_manager.chunk_state(IVec3::ZERO),
+            Some(ChunkState::Unloading)
+        );
+    }
 }
@@ -1,105 +1,462 @@
-use crate::{chunk::*, mesh::MeshData};
+use crate::{
+    chunk::*,
+    material::{
+        TerrainMaterial, TerrainMaterialKey, TerrainMaterialPlugin, TerrainMaterials,
+        CHUNK_FADE_DURATION,
+    },
+    mesh::{ChunkMeshData, ChunkMeshInput, MeshSettings},
+    voxel::RenderLayer,
+};
+use bevy::log::info_span;
 use bevy::prelude::*;
-use bevy_flycam::FlyCam;
+use bevy::render::primitives::Frustum;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use bevy::utils::{Duration, Instant};
+use futures_lite::future;
 use std::collections::HashMap;
 
-use super::ChunkManager;
+use super::budget::ChunkBudget;
+use super::culling::{chunk_aabb, cull_chunks, CaveCulling};
+use super::events::{ChunkGenerated, ChunkMeshed, ChunkSpawned, ChunkUnloaded, VoxelChanged};
+use super::{ChunkManager, ChunkState, LoadArea, RenderDistance};
 
 pub struct ChunkManagerPlugin;
 
 impl Plugin for ChunkManagerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(TerrainMaterialPlugin)
+            .add_plugin(ChunkStreamingPlugin);
+    }
+}
+
+/// Loads, meshes and spawns chunks, without the rendering setup of
+/// `ChunkManagerPlugin`. Headless apps register the `Mesh` and
+/// `TerrainMaterial` assets and insert `TerrainMaterials` themselves.
+pub struct ChunkStreamingPlugin;
+
+impl Plugin for ChunkStreamingPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ChunkManager>()
             .init_resource::<ChunkEntityMap>()
-            .add_system(spawn_chunks)
-            .add_system(despawn_chunks)
-            .add_system(reload_chunks)
-            .add_system(update_chunk_manager);
+            .init_resource::<ChunkGenerateTasks>()
+            .init_resource::<ChunkMeshTasks>()
+            .init_resource::<MeshSettings>()
+            .init_resource::<ChunkBudget>()
+            .init_resource::<RenderDistance>()
+            .init_resource::<CaveCulling>()
+            .add_event::<ChunkGenerated>()
+            .add_event::<ChunkMeshed>()
+            .add_event::<ChunkSpawned>()
+            .add_event::<ChunkUnloaded>()
+            .add_event::<VoxelChanged>()
+            .add_startup_system(spawn_chunk_root)
+            .add_system(load_chunks)
+            .add_system(mesh_chunks)
+            // Chunk entities spawned in one frame are only in the entity map
+            // from the next. Chunks are unloaded before meshes are spawned,
+            // so a chunk is never unloaded in the frame its entity spawns.
+            .add_system(
+                spawn_chunks
+                    .after(sync_chunk_entity_map)
+                    .after(update_chunk_manager),
+            )
+            .add_system(finish_chunk_fades)
+            .add_system(despawn_chunks.after(sync_chunk_entity_map))
+            .add_system(remesh_chunks_on_settings_change)
+            .add_system(update_chunk_manager)
+            .add_system(send_voxel_changed_events)
+            .add_system(sync_chunk_entity_map)
+            .add_system(cull_chunks);
     }
 }
 
-#[derive(Resource)]
+/// Position of a chunk entity's chunk, in voxels.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Deref)]
+pub struct ChunkPosition(pub IVec3);
+
+/// Render layer of one of the meshes spawned as children of a chunk entity.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Deref)]
+pub struct ChunkLayer(pub RenderLayer);
+
+/// Parent of every chunk entity.
+#[derive(Component)]
+pub struct ChunkRoot;
+
+/// Chunk entities by chunk position, kept in sync with the entities that
+/// have a `ChunkPosition` from the frame after they're spawned.
+#[derive(Resource, Default)]
 pub struct ChunkEntityMap(HashMap<IVec3, Entity>);
 
-impl Default for ChunkEntityMap {
-    fn default() -> Self {
-        Self(HashMap::default())
+impl ChunkEntityMap {
+    pub fn get(&self, chunk_position: IVec3) -> Option<Entity> {
+        self.0.get(&chunk_position).copied()
     }
 }
 
+// A chunk fading in shares a material with the chunks spawned around the same
+// time until the fade has finished.
+#[derive(Component)]
+pub struct ChunkFade {
+    end: f32,
+}
+
+// Chunks being generated on the async compute pool. Dropping a task cancels
+// it. Tasks also return how long they took.
+#[derive(Resource, Default)]
+pub struct ChunkGenerateTasks(HashMap<IVec3, Task<(Chunk, Duration)>>);
+
+// Meshes being generated on the async compute pool.
+#[derive(Resource, Default)]
+pub struct ChunkMeshTasks(HashMap<IVec3, Task<(ChunkMeshData, Duration)>>);
+
+/// Keeps the chunks around an entity loaded, such as a player, a camera or
+/// an area where the game simulates entities. The loaded area is the union of
+/// all loaders' areas.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct ChunkLoader {
+    /// Load distance in chunks, or `RenderDistance::load` if `None`.
+    pub radius: Option<i32>,
+}
+
+fn spawn_chunk_root(mut commands: Commands) {
+    commands.spawn((SpatialBundle::default(), ChunkRoot, Name::new("Chunks")));
+}
+
 pub fn update_chunk_manager(
     mut chunk_manager: ResMut<ChunkManager>,
-    player_pos_query: Query<&Transform, With<FlyCam>>,
+    render_distance: Res<RenderDistance>,
+    loaders: Query<(&GlobalTransform, &ChunkLoader, Option<&Frustum>)>,
 ) {
-    if let Ok(player_position) = player_pos_query.get_single() {
-        let player_chunk_position = player_position.translation / CHUNK_SIZE as f32;
-        let player_chunk_position = player_chunk_position.floor().as_ivec3() * CHUNK_SIZE;
-        chunk_manager.update(player_chunk_position);
+    if render_distance.is_changed() {
+        chunk_manager.set_render_distance(*render_distance);
     }
+
+    let load_areas = loaders
+        .iter()
+        .map(|(transform, loader, frustum)| {
+            let chunk_position = transform.translation() / CHUNK_SIZE as f32;
+
+            LoadArea {
+                chunk_position: chunk_position.floor().as_ivec3() * CHUNK_SIZE,
+                radius: loader.radius.unwrap_or(render_distance.load),
+                frustum: frustum.cloned(),
+            }
+        })
+        .collect();
+
+    chunk_manager.update(load_areas);
 }
 
-pub fn reload_chunks(
-    mut commands: Commands,
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+
+    (result, start.elapsed())
+}
+
+// Adds the chunks that have finished generating and starts generating the
+// queued ones, as far as the budget allows. Adding a chunk lights it on the
+// main thread, so it's paid for from the same budget.
+pub fn load_chunks(
     mut chunk_manager: ResMut<ChunkManager>,
-    chunk_entity_map: ResMut<ChunkEntityMap>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut generate_tasks: ResMut<ChunkGenerateTasks>,
+    budget: Res<ChunkBudget>,
+    mut chunk_generated: EventWriter<ChunkGenerated>,
 ) {
-    let reload_queue = chunk_manager.reload_queue.clone();
+    let mut budget = budget.generating.start();
 
-    for chunk in reload_queue {
-        chunk_manager.reload_queue.pop_front();
+    generate_tasks.0.retain(|chunk_position, task| {
+        if chunk_manager.chunk_state(*chunk_position) != Some(ChunkState::Generating) {
+            return false;
+        }
 
-        if let Some(id) = chunk_entity_map.0.get(&chunk.position) {
-            let mesh_data = MeshData::generate_marching_cubes(&chunk, &chunk_manager);
-            let mesh = mesh_data.create_mesh();
-            // println!("{:?}", meshes.add(mesh).is_strong());
-            commands
-                .entity(*id)
-                .insert((meshes.add(mesh), materials.add(Color::RED.into())));
+        // Finished chunks are left for the next frame once time is up.
+        if budget.is_exhausted() {
+            return true;
+        }
+
+        let Some((chunk, duration)) = future::block_on(future::poll_once(task)) else {
+            return true;
+        };
+
+        let position = chunk.position;
+        if chunk_manager.load_chunk(chunk) {
+            chunk_generated.send(ChunkGenerated { position, duration });
+            budget.spend();
         }
+
+        false
+    });
+
+    let thread_pool = AsyncComputeTaskPool::get();
+
+    while !budget.is_exhausted() {
+        let Some((chunk_position, lod)) = chunk_manager.start_generating() else {
+            break;
+        };
+
+        let task = thread_pool.spawn(async move { timed(|| Chunk::new(chunk_position, lod)) });
+        generate_tasks.0.insert(chunk_position, task);
+        budget.spend();
+    }
+}
+
+pub fn mesh_chunks(
+    mut chunk_manager: ResMut<ChunkManager>,
+    mut mesh_tasks: ResMut<ChunkMeshTasks>,
+    mesh_settings: Res<MeshSettings>,
+    budget: Res<ChunkBudget>,
+) {
+    let thread_pool = AsyncComputeTaskPool::get();
+    let mut budget = budget.meshing.start();
+
+    while !budget.is_exhausted() {
+        let Some(chunk_position) = chunk_manager.take_meshable_chunk() else {
+            break;
+        };
+
+        if let Some(input) = ChunkMeshInput::new(chunk_position, &chunk_manager) {
+            let mesh_settings = *mesh_settings;
+            let task = thread_pool
+                .spawn(async move { timed(|| ChunkMeshData::generate(mesh_settings, &input)) });
+
+            mesh_tasks.0.insert(chunk_position, task);
+            budget.spend();
+        }
+    }
+}
+
+pub fn remesh_chunks_on_settings_change(
+    mut chunk_manager: ResMut<ChunkManager>,
+    mesh_settings: Res<MeshSettings>,
+) {
+    if mesh_settings.is_changed() && !mesh_settings.is_added() {
+        chunk_manager.remesh_all();
     }
 }
 
+// Spawns an entity for each newly meshed chunk, with a child mesh for each
+// of its render layers, or updates the meshes of a chunk that was meshed again
+// in place. Finished meshes wait until the terrain materials are ready. New
+// chunks fade in so that they don't pop into view.
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_chunks(
     mut commands: Commands,
     mut chunk_manager: ResMut<ChunkManager>,
-    mut chunk_entity_map: ResMut<ChunkEntityMap>,
+    chunk_entity_map: Res<ChunkEntityMap>,
+    mut mesh_tasks: ResMut<ChunkMeshTasks>,
+    mesh_settings: Res<MeshSettings>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    terrain_materials: Option<ResMut<TerrainMaterials>>,
+    chunk_children: Query<&Children>,
+    chunk_layers: Query<(&ChunkLayer, &Handle<Mesh>)>,
+    chunk_root: Query<Entity, With<ChunkRoot>>,
+    time: Res<Time>,
+    budget: Res<ChunkBudget>,
+    mut chunk_meshed: EventWriter<ChunkMeshed>,
+    mut chunk_spawned: EventWriter<ChunkSpawned>,
 ) {
-    let load_queue = chunk_manager.load_queue.clone();
+    let Some(mut terrain_materials) = terrain_materials else {
+        return;
+    };
+    let Ok(chunk_root) = chunk_root.get_single() else {
+        return;
+    };
+
+    let mut budget = budget.spawning.start();
+
+    mesh_tasks.0.retain(|chunk_position, task| {
+        if budget.is_exhausted() {
+            return true;
+        }
+
+        let Some((chunk_mesh_data, duration)) = future::block_on(future::poll_once(task)) else {
+            return true;
+        };
+
+        if !chunk_manager.active_chunks.contains_key(chunk_position) {
+            return false;
+        }
+
+        let spawn_start = Instant::now();
+        let _span = info_span!("spawn_chunk", position = ?chunk_position).entered();
+
+        let scale = chunk_manager.active_chunks[chunk_position].scale();
+        chunk_manager.finish_meshing(*chunk_position);
+        budget.spend();
+
+        // Layers added to a chunk that's already visible appear straight
+        // away.
+        let (id, fade_start) = match chunk_entity_map.get(*chunk_position) {
+            Some(id) => (id, None),
+            None => {
+                let id = commands
+                    .spawn((
+                        SpatialBundle::default(),
+                        ChunkPosition(*chunk_position),
+                        ChunkFade {
+                            end: time.elapsed_seconds() + CHUNK_FADE_DURATION,
+                        },
+                    ))
+                    .set_parent(chunk_root)
+                    .id();
+
+                chunk_spawned.send(ChunkSpawned {
+                    position: *chunk_position,
+                    entity: id,
+                });
+
+                (id, Some(time.elapsed_seconds_wrapped()))
+            }
+        };
 
-    for chunk in load_queue {
-        chunk_manager.load_chunk();
+        commands.entity(id).insert(chunk_mesh_data.connectivity);
 
-        let mesh_data = MeshData::generate_marching_cubes(&chunk, &chunk_manager);
-        let mesh = mesh_data.create_mesh();
+        // Bevy only computes the bounds of a mesh when it's first added, and
+        // chunks change their level of detail in place.
+        let aabb = chunk_aabb(*chunk_position, scale);
 
-        let id = commands
-            .spawn(PbrBundle {
-                mesh: meshes.add(mesh),
-                material: materials.add(Color::GREEN.into()),
-                ..default()
+        let mut existing_layers = chunk_children
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter_map(|child| {
+                let (layer, mesh) = chunk_layers.get(*child).ok()?;
+                Some((layer.0, (*child, mesh)))
             })
-            .id();
+            .collect::<HashMap<_, _>>();
+
+        for (layer, mesh_data) in chunk_mesh_data.layers {
+            let mesh = mesh_data.create_mesh(mesh_settings.shading);
+
+            if let Some((child, handle)) = existing_layers.remove(&layer) {
+                match meshes.get_mut(handle) {
+                    Some(existing_mesh) => *existing_mesh = mesh,
+                    None => {
+                        commands.entity(child).insert(meshes.add(mesh));
+                    }
+                }
+                commands.entity(child).insert(aabb);
+                continue;
+            }
+
+            let key = match fade_start {
+                Some(fade_start) => TerrainMaterialKey::fading(layer, fade_start),
+                None => TerrainMaterialKey::new(layer),
+            };
+
+            commands
+                .spawn((
+                    MaterialMeshBundle::<TerrainMaterial> {
+                        mesh: meshes.add(mesh),
+                        material: terrain_materials.get_or_create(key, &mut materials),
+                        ..default()
+                    },
+                    ChunkLayer(layer),
+                    aabb,
+                ))
+                .set_parent(id);
+        }
+
+        // Layers the chunk no longer has any surfaces in.
+        for (child, _) in existing_layers.into_values() {
+            commands.entity(child).despawn_recursive();
+        }
+
+        chunk_meshed.send(ChunkMeshed {
+            position: *chunk_position,
+            duration,
+            spawn_duration: spawn_start.elapsed(),
+        });
+
+        false
+    });
+}
 
-        chunk_entity_map.0.insert(chunk.position, id);
+// Switches the meshes of chunks that have faded in to the materials for faded
+// in chunks, and drops the materials of fade groups that have finished.
+pub fn finish_chunk_fades(
+    mut commands: Commands,
+    fading_chunks: Query<(Entity, &ChunkFade, Option<&Children>)>,
+    chunk_layers: Query<&ChunkLayer>,
+    terrain_materials: Option<ResMut<TerrainMaterials>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    time: Res<Time>,
+) {
+    let Some(mut terrain_materials) = terrain_materials else {
+        return;
+    };
+
+    for (id, fade, children) in &fading_chunks {
+        if time.elapsed_seconds() < fade.end {
+            continue;
+        }
+
+        for child in children.into_iter().flatten() {
+            if let Ok(layer) = chunk_layers.get(*child) {
+                let material = terrain_materials
+                    .get_or_create(TerrainMaterialKey::new(layer.0), &mut materials);
+                commands.entity(*child).insert(material);
+            }
+        }
+
+        commands.entity(id).remove::<ChunkFade>();
     }
+
+    let elapsed_seconds_wrapped = time.elapsed_seconds_wrapped();
+    terrain_materials
+        .retain(|key| key.fade_group.is_none() || !key.has_faded_in(elapsed_seconds_wrapped));
 }
 
 pub fn despawn_chunks(
     mut commands: Commands,
     mut chunk_manager: ResMut<ChunkManager>,
-    mut chunk_entity_map: ResMut<ChunkEntityMap>,
+    chunk_entity_map: Res<ChunkEntityMap>,
+    mut mesh_tasks: ResMut<ChunkMeshTasks>,
+    budget: Res<ChunkBudget>,
+    mut chunk_unloaded: EventWriter<ChunkUnloaded>,
 ) {
-    let unload_queue = chunk_manager.unload_queue.clone();
+    let mut budget = budget.despawning.start();
 
-    for chunk in unload_queue {
-        if let Some(id) = chunk_entity_map.0.remove(&chunk.position) {
-            commands.entity(id).despawn();
+    while !budget.is_exhausted() {
+        let Some(chunk) = chunk_manager.unload_queue.front() else {
+            break;
+        };
+
+        let _span = info_span!("despawn_chunk", position = ?chunk.position).entered();
+
+        if let Some(id) = chunk_entity_map.get(chunk.position) {
+            commands.entity(id).despawn_recursive();
         }
 
-        chunk_manager.unload_chunk();
+        mesh_tasks.0.remove(&chunk.position);
+        if let Some(chunk) = chunk_manager.unload_chunk() {
+            chunk_unloaded.send(ChunkUnloaded { chunk });
+        }
+        budget.spend();
+    }
+}
+
+pub fn send_voxel_changed_events(
+    mut chunk_manager: ResMut<ChunkManager>,
+    mut voxel_changed: EventWriter<VoxelChanged>,
+) {
+    voxel_changed.send_batch(chunk_manager.take_voxel_changes());
+}
+
+// The only writer of the chunk entity map, so the map follows the chunk
+// entities however they're spawned or despawned.
+pub fn sync_chunk_entity_map(
+    mut chunk_entity_map: ResMut<ChunkEntityMap>,
+    added_chunks: Query<(Entity, &ChunkPosition), Added<ChunkPosition>>,
+    mut removed_chunks: RemovedComponents<ChunkPosition>,
+) {
+    for id in removed_chunks.iter() {
+        chunk_entity_map.0.retain(|_, chunk_id| *chunk_id != id);
+    }
+
+    for (id, chunk_position) in &added_chunks {
+        chunk_entity_map.0.insert(chunk_position.0, id);
     }
 }
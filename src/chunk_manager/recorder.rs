@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+use bevy::utils::{Duration, Instant};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use super::events::{ChunkGenerated, ChunkMeshed};
+use super::ChunkManager;
+
+/// File format of the chunk timings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingFormat {
+    /// A header row followed by one row per stage of each chunk.
+    Csv,
+    /// JSON Lines: one JSON object per line for each stage of each chunk.
+    /// The file as a whole isn't a JSON document.
+    JsonLines,
+}
+
+/// Writes how long each chunk spent generating, meshing and spawning to a
+/// file for the length of the session, one record per stage, so mesher and
+/// generator changes can be compared between runs.
+///
+/// Records hold the seconds since recording started, the stage, the chunk
+/// position, its level of detail and the duration in milliseconds.
+pub struct ChunkTimingRecorderPlugin {
+    pub path: PathBuf,
+    pub format: TimingFormat,
+}
+
+impl ChunkTimingRecorderPlugin {
+    /// Records to `path`, as JSON Lines if it ends in `.jsonl` and as CSV
+    /// otherwise.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let format = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("jsonl") => TimingFormat::JsonLines,
+            _ => TimingFormat::Csv,
+        };
+
+        Self { path, format }
+    }
+}
+
+impl Plugin for ChunkTimingRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ChunkTimingRecorder {
+            path: self.path.clone(),
+            format: self.format,
+            writer: None,
+            start: Instant::now(),
+        })
+        .add_startup_system(start_recording)
+        .add_system(record_chunk_timings);
+    }
+}
+
+#[derive(Resource)]
+struct ChunkTimingRecorder {
+    path: PathBuf,
+    format: TimingFormat,
+    // `None` before recording starts and after writing has failed.
+    writer: Option<BufWriter<File>>,
+    start: Instant,
+}
+
+impl ChunkTimingRecorder {
+    fn write(&mut self, stage: &str, position: IVec3, lod: Option<u32>, duration: Duration) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+
+        let time = self.start.elapsed().as_secs_f64();
+        let duration = duration.as_secs_f64() * 1000.0;
+
+        let result = match self.format {
+            TimingFormat::Csv => {
+                let lod = lod.map(|lod| lod.to_string()).unwrap_or_default();
+                writeln!(
+                    writer,
+                    "{time:.6},{stage},{},{},{},{lod},{duration:.6}",
+                    position.x, position.y, position.z
+                )
+            }
+            TimingFormat::JsonLines => {
+                let lod = lod.map_or("null".to_string(), |lod| lod.to_string());
+                writeln!(
+                    writer,
+                    r#"{{"time":{time:.6},"stage":"{stage}","x":{},"y":{},"z":{},"lod":{lod},"duration_ms":{duration:.6}}}"#,
+                    position.x, position.y, position.z
+                )
+            }
+        };
+
+        if let Err(error) = result {
+            self.fail(error);
+        }
+    }
+
+    fn fail(&mut self, error: std::io::Error) {
+        error!(
+            "Stopped recording chunk timings to {}: {error}",
+            self.path.display()
+        );
+        self.writer = None;
+    }
+}
+
+fn start_recording(mut recorder: ResMut<ChunkTimingRecorder>) {
+    let mut writer = match File::create(&recorder.path) {
+        Ok(file) => BufWriter::new(file),
+        Err(error) => {
+            recorder.fail(error);
+            return;
+        }
+    };
+
+    if recorder.format == TimingFormat::Csv {
+        if let Err(error) = writeln!(writer, "time,stage,x,y,z,lod,duration_ms") {
+            recorder.fail(error);
+            return;
+        }
+    }
+
+    info!("Recording chunk timings to {}", recorder.path.display());
+    recorder.writer = Some(writer);
+    recorder.start = Instant::now();
+}
+
+// Flushed every frame, so the file is complete however the app exits.
+fn record_chunk_timings(
+    mut recorder: ResMut<ChunkTimingRecorder>,
+    chunk_manager: Res<ChunkManager>,
+    mut chunk_generated: EventReader<ChunkGenerated>,
+    mut chunk_meshed: EventReader<ChunkMeshed>,
+) {
+    let lod_of = |position| chunk_manager.get_chunk(position).map(|chunk| chunk.lod);
+
+    for event in chunk_generated.iter() {
+        recorder.write(
+            "generate",
+            event.position,
+            lod_of(event.position),
+            event.duration,
+        );
+    }
+
+    for event in chunk_meshed.iter() {
+        let lod = lod_of(event.position);
+        recorder.write("mesh", event.position, lod, event.duration);
+        recorder.write("spawn", event.position, lod, event.spawn_duration);
+    }
+
+    if let Some(writer) = &mut recorder.writer {
+        if let Err(error) = writer.flush() {
+            recorder.fail(error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_mesh_and_spawn_times() {
+        let path = std::env::temp_dir().join(format!("chunk_timings_{}.csv", std::process::id()));
+
+        let mut app = App::new();
+        app.init_resource::<ChunkManager>()
+            .add_event::<ChunkGenerated>()
+            .add_event::<ChunkMeshed>()
+            .add_plugin(ChunkTimingRecorderPlugin::new(&path));
+
+        for x in 0..2 {
+            app.world.send_event(ChunkMeshed {
+                position: IVec3::new(x, 0, 0),
+                duration: Duration::from_millis(2),
+                spawn_duration: Duration::from_millis(1),
+            });
+        }
+        app.update();
+
+        let timings = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut lines = timings.lines();
+
+        assert_eq!(lines.next(), Some("time,stage,x,y,z,lod,duration_ms"));
+        // A mesh and a spawn row for each chunk.
+        assert_eq!(lines.count(), 4);
+    }
+
+    #[test]
+    fn only_jsonl_records_json_lines() {
+        let format = |path| ChunkTimingRecorderPlugin::new(path).format;
+
+        assert_eq!(format("timings.jsonl"), TimingFormat::JsonLines);
+        assert_eq!(format("timings.json"), TimingFormat::Csv);
+        assert_eq!(format("timings.csv"), TimingFormat::Csv);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::chunk::CHUNK_SIZE;
+use crate::chunk_manager::{ChunkManager, ChunkState};
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use bevy::render::render_resource::PrimitiveTopology;
+use bevy::render::view::NoFrustumCulling;
+use bevy_rapier3d::render::DebugRenderContext;
+use std::collections::{HashMap, HashSet};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+
+// Boxes are shrunk by this much on every side, so the borders of
+// neighbouring chunks don't overlap.
+const BORDER_INSET: f32 = 0.05;
+
+const GENERATING_COLOR: Color = Color::YELLOW;
+const GENERATED_COLOR: Color = Color::ORANGE;
+const MESHING_COLOR: Color = Color::CYAN;
+const READY_COLOR: Color = Color::GREEN;
+// Ready chunks waiting to be meshed again.
+const DIRTY_COLOR: Color = Color::FUCHSIA;
+const UNLOADING_COLOR: Color = Color::GRAY;
+// Queued chunks are colored from the next to load to the last.
+const QUEUE_HEAD_COLOR: Vec3 = Vec3::new(1.0, 0.0, 0.0);
+const QUEUE_TAIL_COLOR: Vec3 = Vec3::new(0.0, 0.0, 1.0);
+
+/// Overlay for diagnosing chunk streaming, toggled with F3. Draws the
+/// borders of the chunks around the camera, colored by their stage in the
+/// loading pipeline, with queued chunks forming a heat map of the load
+/// order.
+///
+/// Apps using rapier can add `RapierDebugRenderPlugin::default().disabled()`
+/// to have collider wireframes shown along with the overlay.
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugOverlay>()
+            .add_startup_system(spawn_chunk_borders)
+            .add_system(toggle_debug_overlay)
+            .add_system(draw_chunk_borders.after(toggle_debug_overlay))
+            .add_system(toggle_collider_wireframes.after(toggle_debug_overlay));
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+    /// Distance in chunks around the camera to draw chunk borders in.
+    pub radius: i32,
+    /// Whether rapier's collider wireframes are shown with the overlay.
+    pub colliders: bool,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 8,
+            colliders: true,
+        }
+    }
+}
+
+#[derive(Component)]
+struct ChunkBorders;
+
+fn spawn_chunk_borders(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(Mesh::new(PrimitiveTopology::LineList)),
+            material: materials.add(StandardMaterial {
+                base_color: Color::WHITE,
+                unlit: true,
+                fog_enabled: false,
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        ChunkBorders,
+        NotShadowCaster,
+        // The lines are redrawn around the camera every frame, while the
+        // bounds Bevy computed for the first ones never change.
+        NoFrustumCulling,
+        Name::new("Chunk borders"),
+    ));
+}
+
+fn toggle_debug_overlay(keys: Res<Input<KeyCode>>, mut debug_overlay: ResMut<DebugOverlay>) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        debug_overlay.enabled = !debug_overlay.enabled;
+    }
+}
+
+fn toggle_collider_wireframes(
+    debug_overlay: Res<DebugOverlay>,
+    debug_render_context: Option<ResMut<DebugRenderContext>>,
+) {
+    let Some(mut debug_render_context) = debug_render_context else {
+        return;
+    };
+
+    if debug_overlay.is_changed() {
+        debug_render_context.enabled = debug_overlay.enabled && debug_overlay.colliders;
+    }
+}
+
+// Rebuilds the line mesh of the chunk borders every frame while the overlay
+// is shown.
+fn draw_chunk_borders(
+    debug_overlay: Res<DebugOverlay>,
+    chunk_manager: Res<ChunkManager>,
+    cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    mut chunk_borders: Query<(&Handle<Mesh>, &mut Visibility), With<ChunkBorders>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let Ok((mesh, mut visibility)) = chunk_borders.get_single_mut() else {
+        return;
+    };
+
+    if !debug_overlay.enabled {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Inherited;
+
+    let Some((_, camera_transform)) = cameras.iter().find(|(camera, _)| camera.is_active) else {
+        return;
+    };
+    let Some(mesh) = meshes.get_mut(mesh) else {
+        return;
+    };
+
+    let camera_chunk =
+        ChunkManager::chunk_position_of(camera_transform.translation().floor().as_ivec3());
+    let load_order = chunk_manager
+        .load_queue()
+        .iter()
+        .enumerate()
+        .map(|(i, pos)| (*pos, i))
+        .collect::<HashMap<_, _>>();
+    let mesh_queue = chunk_manager.mesh_queue().iter().collect::<HashSet<_>>();
+
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+
+    for (chunk_position, state) in chunk_manager.chunk_states() {
+        let offset = (chunk_position - camera_chunk) / CHUNK_SIZE;
+        if offset.x.abs() > debug_overlay.radius || offset.z.abs() > debug_overlay.radius {
+            continue;
+        }
+
+        let color = match state {
+            ChunkState::Queued => {
+                let rank = load_order.get(&chunk_position).copied().unwrap_or(0);
+                let t = rank as f32 / load_order.len().max(1) as f32;
+                let color = QUEUE_HEAD_COLOR.lerp(QUEUE_TAIL_COLOR, t);
+                Color::rgb(color.x, color.y, color.z)
+            }
+            ChunkState::Generating => GENERATING_COLOR,
+            ChunkState::Generated => GENERATED_COLOR,
+            ChunkState::Meshing => MESHING_COLOR,
+            ChunkState::Ready if mesh_queue.contains(&chunk_position) => DIRTY_COLOR,
+            ChunkState::Ready => READY_COLOR,
+            ChunkState::Unloading => UNLOADING_COLOR,
+        };
+
+        let min = chunk_position.as_vec3() + BORDER_INSET;
+        let max = (chunk_position + CHUNK_SIZE).as_vec3() - BORDER_INSET;
+        for (start, end) in box_edges(min, max) {
+            positions.extend([start.to_array(), end.to_array()]);
+            colors.extend([color.as_linear_rgba_f32(); 2]);
+        }
+    }
+
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+}
+
+fn box_edges(min: Vec3, max: Vec3) -> [(Vec3, Vec3); 12] {
+    let corner = |i: usize| {
+        Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        )
+    };
+
+    [
+        (0, 1),
+        (2, 3),
+        (4, 5),
+        (6, 7),
+        (0, 2),
+        (1, 3),
+        (4, 6),
+        (5, 7),
+        (0, 4),
+        (1, 5),
+        (2, 6),
+        (3, 7),
+    ]
+    .map(|(a, b)| (corner(a), corner(b)))
+}
@@ -1,28 +1,174 @@
-use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin};
+use crate::chunk_manager::events::{ChunkGenerated, ChunkMeshed};
+use crate::chunk_manager::plugin::ChunkLayer;
+use crate::chunk_manager::ChunkManager;
+use crate::voxel::Voxel;
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics, FrameTimeDiagnosticsPlugin};
 use bevy::prelude::*;
-use bevy_flycam::FlyCam;
+use std::f32::consts::FRAC_PI_4;
 use std::fmt::Write;
+use std::marker::PhantomData;
+use std::mem::size_of;
 
 const FONT_SIZE: f32 = 32.0;
+const DETAIL_FONT_SIZE: f32 = 20.0;
 const FONT_COLOR: Color = Color::WHITE;
 
-pub struct ScreenDiagnosticsPlugin;
+// Distance in voxels up to which the targeted block is shown.
+const TARGET_DISTANCE: f32 = 32.0;
 
-impl Plugin for ScreenDiagnosticsPlugin {
+// Headings clockwise from -Z.
+const DIRECTIONS: [&str; 4] = ["north", "east", "south", "west"];
+
+/// Shows the frame rate, chunk diagnostics and where the entity marked with
+/// `T` is and what it's looking at. Apps can mark their camera with
+/// `DiagnosticsTarget` or track it by a component it already has.
+pub struct ScreenDiagnosticsPlugin<T: Component = DiagnosticsTarget> {
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Component> Default for ScreenDiagnosticsPlugin<T> {
+    fn default() -> Self {
+        Self {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Component> Plugin for ScreenDiagnosticsPlugin<T> {
     fn build(&self, app: &mut App) {
-        app.add_plugin(FrameTimeDiagnosticsPlugin::default())
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_plugin(ChunkDiagnosticsPlugin)
             .add_startup_system(spawn_text)
             .add_system(update_fps_text)
-            .add_system(update_pos_text);
+            .add_system(update_pos_text::<T>)
+            .add_system(update_chunk_text);
+    }
+}
+
+/// Measures the chunk pipeline: chunk counts, queue lengths, the time spent
+/// generating and meshing each chunk, the size of the chunk meshes and the
+/// memory taken up by voxels. Chunk counts, queue lengths and voxel memory
+/// are only measured in apps with a `ChunkManager`.
+pub struct ChunkDiagnosticsPlugin;
+
+impl Plugin for ChunkDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ChunkGenerated>()
+            .add_event::<ChunkMeshed>()
+            .add_startup_system(Self::setup_system)
+            .add_system(Self::diagnostic_system);
+    }
+}
+
+impl ChunkDiagnosticsPlugin {
+    pub const ACTIVE_CHUNKS: DiagnosticId =
+        DiagnosticId::from_u128(260799506931556225707460548149888108546);
+    pub const LOAD_QUEUE: DiagnosticId =
+        DiagnosticId::from_u128(259170826034008442924724371753964879812);
+    pub const UNLOAD_QUEUE: DiagnosticId =
+        DiagnosticId::from_u128(314332118832974942896656108624150342719);
+    /// Chunks waiting to be meshed, including loaded chunks to mesh again.
+    pub const MESH_QUEUE: DiagnosticId =
+        DiagnosticId::from_u128(74086042971667998737299104220175864894);
+    /// Time spent generating a chunk, averaged over the last chunks.
+    pub const GENERATION_TIME: DiagnosticId =
+        DiagnosticId::from_u128(310756821883615620732857489446792204282);
+    /// Time spent meshing a chunk, averaged over the last chunks.
+    pub const MESHING_TIME: DiagnosticId =
+        DiagnosticId::from_u128(141588028128271922918253689457472852341);
+    pub const VERTICES: DiagnosticId =
+        DiagnosticId::from_u128(244517149575165000135297503505557041855);
+    pub const TRIANGLES: DiagnosticId =
+        DiagnosticId::from_u128(105794003041649190557852788777374348377);
+    /// Rough size of the voxels of all active chunks.
+    pub const VOXEL_MEMORY: DiagnosticId =
+        DiagnosticId::from_u128(180510320042267938334928168256623683185);
+
+    pub fn setup_system(mut diagnostics: ResMut<Diagnostics>) {
+        diagnostics.add(Diagnostic::new(Self::ACTIVE_CHUNKS, "active_chunks", 20));
+        diagnostics.add(Diagnostic::new(Self::LOAD_QUEUE, "load_queue", 20));
+        diagnostics.add(Diagnostic::new(Self::UNLOAD_QUEUE, "unload_queue", 20));
+        diagnostics.add(Diagnostic::new(Self::MESH_QUEUE, "mesh_queue", 20));
+        diagnostics.add(
+            Diagnostic::new(Self::GENERATION_TIME, "chunk_generation_time", 100).with_suffix("ms"),
+        );
+        diagnostics
+            .add(Diagnostic::new(Self::MESHING_TIME, "chunk_meshing_time", 100).with_suffix("ms"));
+        diagnostics.add(Diagnostic::new(Self::VERTICES, "vertices", 20));
+        diagnostics.add(Diagnostic::new(Self::TRIANGLES, "triangles", 20));
+        diagnostics.add(Diagnostic::new(Self::VOXEL_MEMORY, "voxel_memory", 20).with_suffix("MiB"));
+    }
+
+    pub fn diagnostic_system(
+        mut diagnostics: ResMut<Diagnostics>,
+        chunk_manager: Option<Res<ChunkManager>>,
+        mut chunk_generated: EventReader<ChunkGenerated>,
+        mut chunk_meshed: EventReader<ChunkMeshed>,
+        chunk_meshes: Query<&Handle<Mesh>, With<ChunkLayer>>,
+        meshes: Res<Assets<Mesh>>,
+    ) {
+        if let Some(chunk_manager) = &chunk_manager {
+            diagnostics
+                .add_measurement(Self::ACTIVE_CHUNKS, || chunk_manager.chunks().len() as f64);
+            diagnostics
+                .add_measurement(Self::LOAD_QUEUE, || chunk_manager.load_queue().len() as f64);
+            diagnostics.add_measurement(Self::UNLOAD_QUEUE, || {
+                chunk_manager.unload_queue().len() as f64
+            });
+            diagnostics
+                .add_measurement(Self::MESH_QUEUE, || chunk_manager.mesh_queue().len() as f64);
+
+            diagnostics.add_measurement(Self::VOXEL_MEMORY, || {
+                let voxels = chunk_manager
+                    .chunks()
+                    .map(|chunk| chunk.voxel_data.voxels.capacity())
+                    .sum::<usize>();
+                let bytes = voxels * size_of::<(IVec3, Voxel)>();
+
+                bytes as f64 / (1024.0 * 1024.0)
+            });
+        }
+
+        for event in chunk_generated.iter() {
+            diagnostics.add_measurement(Self::GENERATION_TIME, || {
+                event.duration.as_secs_f64() * 1000.0
+            });
+        }
+        for event in chunk_meshed.iter() {
+            diagnostics
+                .add_measurement(Self::MESHING_TIME, || event.duration.as_secs_f64() * 1000.0);
+        }
+
+        let mut vertices = 0;
+        let mut triangles = 0;
+        for mesh in chunk_meshes.iter().filter_map(|handle| meshes.get(handle)) {
+            vertices += mesh.count_vertices();
+            // Flat shaded meshes have no indices.
+            triangles += mesh
+                .indices()
+                .map_or(mesh.count_vertices(), |indices| indices.len())
+                / 3;
+        }
+        diagnostics.add_measurement(Self::VERTICES, || vertices as f64);
+        diagnostics.add_measurement(Self::TRIANGLES, || triangles as f64);
     }
 }
 
+/// Marks the entity whose position, facing and targeted block are shown on
+/// screen by the default `ScreenDiagnosticsPlugin`. When several entities
+/// are marked, the one with the lowest id is shown.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct DiagnosticsTarget;
+
 #[derive(Component)]
 struct ScreenDiagnosticsText;
 
 #[derive(Component)]
 struct PlayerPositionText;
 
+#[derive(Component)]
+struct ChunkDiagnosticsText;
+
 fn spawn_text(mut commands: Commands, asset_server: Res<AssetServer>) {
     let font = asset_server.load("fonts/Roboto-Regular.ttf");
     commands
@@ -68,6 +214,28 @@ fn spawn_text(mut commands: Commands, asset_server: Res<AssetServer>) {
             }),
         )
         .insert(PlayerPositionText);
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "Chunks: ...".to_string(),
+                TextStyle {
+                    font: font.clone(),
+                    font_size: DETAIL_FONT_SIZE,
+                    color: FONT_COLOR,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(5.0),
+                    left: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(ChunkDiagnosticsText);
 }
 
 fn update_fps_text(
@@ -90,16 +258,172 @@ fn update_fps_text(
     }
 }
 
-fn update_pos_text(
+fn update_pos_text<T: Component>(
     mut pos_text_query: Query<&mut Text, With<PlayerPositionText>>,
-    player_transform_query: Query<&Transform, With<FlyCam>>,
+    target_query: Query<(Entity, &GlobalTransform), With<T>>,
+    chunk_manager: Option<Res<ChunkManager>>,
+) {
+    let Ok(mut text) = pos_text_query.get_single_mut() else {
+        return;
+    };
+    let value = &mut text.sections[0].value;
+    value.clear();
+
+    let Some((_, transform)) = target_query.iter().min_by_key(|(id, _)| *id) else {
+        write!(value, "No diagnostics target").unwrap();
+        return;
+    };
+
+    let targets = target_query.iter().len();
+    if targets > 1 {
+        writeln!(value, "Showing 1 of {} diagnostics targets", targets).unwrap();
+    }
+
+    let pos = transform.translation();
+    let forward = transform.forward();
+
+    writeln!(value, "X: {:.2} Y: {:.2} Z: {:.2}", pos.x, pos.y, pos.z).unwrap();
+
+    if chunk_manager.is_some() {
+        let chunk_position = ChunkManager::chunk_position_of(pos.floor().as_ivec3());
+        writeln!(
+            value,
+            "Chunk: {} {} {}",
+            chunk_position.x, chunk_position.y, chunk_position.z
+        )
+        .unwrap();
+    }
+
+    let heading = forward.x.atan2(-forward.z);
+    let direction = (heading / (2.0 * FRAC_PI_4)).round() as i32;
+    write!(
+        value,
+        "Facing: {} ({}° / {}°)",
+        DIRECTIONS[direction.rem_euclid(4) as usize],
+        (heading.to_degrees().round() as i32).rem_euclid(360),
+        forward.y.asin().to_degrees().round() as i32
+    )
+    .unwrap();
+
+    let Some(chunk_manager) = chunk_manager else {
+        return;
+    };
+
+    let target = chunk_manager
+        .raycast(pos, forward, TARGET_DISTANCE)
+        .and_then(|target| Some((target, chunk_manager.get_voxel_at_global_position(target)?)));
+    match target {
+        Some((target, voxel)) => write!(
+            value,
+            "\nTarget: {:?} at {} {} {}",
+            voxel.block_type, target.x, target.y, target.z
+        )
+        .unwrap(),
+        None => write!(value, "\nTarget: none").unwrap(),
+    }
+}
+
+fn update_chunk_text(
+    diagnostics: Res<Diagnostics>,
+    mut chunk_text_query: Query<&mut Text, With<ChunkDiagnosticsText>>,
+    chunk_manager: Option<Res<ChunkManager>>,
 ) {
-    let pos = player_transform_query.get_single().unwrap().translation;
+    let value_of = |id| {
+        diagnostics
+            .get(id)
+            .and_then(|diagnostic| diagnostic.value())
+            .unwrap_or(0.0)
+    };
+    let average_of = |id| {
+        diagnostics
+            .get(id)
+            .and_then(|diagnostic| diagnostic.average())
+            .unwrap_or(0.0)
+    };
 
-    if let Ok(mut text) = pos_text_query.get_single_mut() {
+    if let Ok(mut text) = chunk_text_query.get_single_mut() {
         let value = &mut text.sections[0].value;
         value.clear();
 
-        write!(value, "X: {:.2} Y: {:.2} Z: {:.2}", pos.x, pos.y, pos.z).unwrap();
+        if chunk_manager.is_some() {
+            writeln!(
+                value,
+                "Chunks: {:.0}",
+                value_of(ChunkDiagnosticsPlugin::ACTIVE_CHUNKS)
+            )
+            .unwrap();
+            writeln!(
+                value,
+                "Queues: load {:.0}, unload {:.0}, mesh {:.0}",
+                value_of(ChunkDiagnosticsPlugin::LOAD_QUEUE),
+                value_of(ChunkDiagnosticsPlugin::UNLOAD_QUEUE),
+                value_of(ChunkDiagnosticsPlugin::MESH_QUEUE)
+            )
+            .unwrap();
+        }
+        writeln!(
+            value,
+            "Per chunk: generation {:.2} ms, meshing {:.2} ms",
+            average_of(ChunkDiagnosticsPlugin::GENERATION_TIME),
+            average_of(ChunkDiagnosticsPlugin::MESHING_TIME)
+        )
+        .unwrap();
+        write!(
+            value,
+            "Meshes: {:.0} vertices, {:.0} triangles",
+            value_of(ChunkDiagnosticsPlugin::VERTICES),
+            value_of(ChunkDiagnosticsPlugin::TRIANGLES)
+        )
+        .unwrap();
+
+        if chunk_manager.is_some() {
+            write!(
+                value,
+                "\nVoxels: {:.1} MiB",
+                value_of(ChunkDiagnosticsPlugin::VOXEL_MEMORY)
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs `update_pos_text` once with targets at `positions` and returns the
+    // text it wrote.
+    fn pos_text(positions: &[Vec3]) -> String {
+        let mut world = World::new();
+        world.init_resource::<ChunkManager>();
+        world.spawn((Text::from_section("", default()), PlayerPositionText));
+        for position in positions {
+            world.spawn((
+                GlobalTransform::from_translation(*position),
+                DiagnosticsTarget,
+            ));
+        }
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(update_pos_text::<DiagnosticsTarget>);
+        schedule.run(&mut world);
+
+        let mut text = world.query::<&Text>();
+        text.single(&world).sections[0].value.clone()
+    }
+
+    #[test]
+    fn pos_text_without_target() {
+        assert_eq!(pos_text(&[]), "No diagnostics target");
+    }
+
+    #[test]
+    fn pos_text_with_several_targets() {
+        let text = pos_text(&[Vec3::new(1.0, 2.0, 3.0), Vec3::new(4.0, 5.0, 6.0)]);
+        let mut lines = text.lines();
+
+        assert_eq!(lines.next(), Some("Showing 1 of 2 diagnostics targets"));
+        // The target with the lowest entity id is shown.
+        assert_eq!(lines.next(), Some("X: 1.00 Y: 2.00 Z: 3.00"));
     }
 }
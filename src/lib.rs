@@ -1,7 +1,11 @@
 pub mod camera;
 pub mod chunk;
 pub mod chunk_manager;
+pub mod debug;
 pub mod diagnostic;
+pub mod light;
+pub mod material;
 pub mod mesh;
+pub mod sky;
 pub mod tables;
 pub mod voxel;
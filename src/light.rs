@@ -0,0 +1,426 @@
+use crate::chunk::CHUNK_SIZE;
+use crate::chunk_manager::ChunkManager;
+use crate::voxel::Voxel;
+use bevy::log::info_span;
+use bevy::prelude::*;
+use std::collections::{HashSet, VecDeque};
+
+pub const MAX_LIGHT: u8 = 15;
+
+const DIRECTIONS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightChannel {
+    /// Light from the sky, which falls straight down without fading.
+    Sky,
+    /// Light emitted by blocks.
+    Block,
+}
+
+impl LightChannel {
+    pub fn get(self, voxel: &Voxel) -> u8 {
+        match self {
+            LightChannel::Sky => voxel.sky_light,
+            LightChannel::Block => voxel.block_light,
+        }
+    }
+
+    fn set(self, voxel: &mut Voxel, level: u8) {
+        match self {
+            LightChannel::Sky => voxel.sky_light = level,
+            LightChannel::Block => voxel.block_light = level,
+        }
+    }
+
+    // Level passed on from a voxel lit at `level` to its neighbour in
+    // `direction`.
+    fn spread(self, level: u8, direction: IVec3) -> u8 {
+        if self == LightChannel::Sky && level == MAX_LIGHT && direction == IVec3::NEG_Y {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+// Light is only propagated through full detail chunks. Coarser chunks and
+// unloaded space act as walls.
+fn lit_voxel(chunk_manager: &ChunkManager, pos: IVec3) -> Option<&Voxel> {
+    let chunk = chunk_manager.get_chunk(ChunkManager::chunk_position_of(pos))?;
+
+    if chunk.lod != 0 {
+        return None;
+    }
+
+    chunk_manager.get_voxel_at_global_position(pos)
+}
+
+fn lit_voxel_mut(chunk_manager: &mut ChunkManager, pos: IVec3) -> Option<&mut Voxel> {
+    lit_voxel(chunk_manager, pos)?;
+    chunk_manager.get_voxel_at_global_position_mut(pos)
+}
+
+// Voxels below an unloaded chunk are open to the sky.
+fn is_below_sky(chunk_manager: &ChunkManager, pos: IVec3) -> bool {
+    let above = ChunkManager::chunk_position_of(pos + IVec3::Y);
+    chunk_manager.get_chunk(above).is_none()
+}
+
+/// Lights a newly loaded chunk: sky light falls in from above, emissive
+/// blocks start glowing and light from already lit neighbours flows in.
+/// Chunks at a coarser level of detail are simply lit by the sky.
+///
+/// Returns the positions outside the chunk whose light changed.
+pub fn light_chunk(chunk_manager: &mut ChunkManager, chunk_position: IVec3) -> HashSet<IVec3> {
+    let _span = info_span!("light_chunk", ?chunk_position).entered();
+    let mut changed = HashSet::new();
+
+    let Some(chunk) = chunk_manager.get_chunk_mut(chunk_position) else {
+        return changed;
+    };
+
+    if chunk.lod != 0 {
+        for voxel in chunk.voxel_data.voxels.values_mut() {
+            if !voxel.is_opaque() {
+                voxel.sky_light = MAX_LIGHT;
+            }
+        }
+
+        return changed;
+    }
+
+    let mut sky_queue = VecDeque::new();
+    let mut block_queue = VecDeque::new();
+
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let pos = chunk_position + IVec3::new(x, y, z);
+                let is_top = y == CHUNK_SIZE - 1 && is_below_sky(chunk_manager, pos);
+                let voxel = lit_voxel_mut(chunk_manager, pos).unwrap();
+
+                if is_top && !voxel.is_opaque() {
+                    voxel.sky_light = MAX_LIGHT;
+                    sky_queue.push_back(pos);
+                }
+
+                let emission = voxel.block_type.light_emission();
+                if emission > 0 {
+                    voxel.block_light = emission;
+                    block_queue.push_back(pos);
+                }
+
+                // Pull in light from lit voxels across the chunk's faces.
+                let on_border = [x, y, z].iter().any(|c| *c == 0 || *c == CHUNK_SIZE - 1);
+                if !on_border {
+                    continue;
+                }
+
+                for direction in DIRECTIONS {
+                    let neighbour_pos = pos + direction;
+                    if ChunkManager::chunk_position_of(neighbour_pos) == chunk_position {
+                        continue;
+                    }
+
+                    if let Some(neighbour) = lit_voxel(chunk_manager, neighbour_pos) {
+                        if neighbour.sky_light > 0 {
+                            sky_queue.push_back(neighbour_pos);
+                        }
+                        if neighbour.block_light > 0 {
+                            block_queue.push_back(neighbour_pos);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    propagate(chunk_manager, LightChannel::Sky, sky_queue, &mut changed);
+    propagate(
+        chunk_manager,
+        LightChannel::Block,
+        block_queue,
+        &mut changed,
+    );
+
+    changed.retain(|pos| ChunkManager::chunk_position_of(*pos) != chunk_position);
+    changed
+}
+
+/// Updates light after the voxel at `pos` was replaced by `old`. Light that
+/// came from or passed through the old voxel is removed before light flows
+/// back in around the new one.
+///
+/// Returns the positions whose light changed.
+pub fn update_voxel(chunk_manager: &mut ChunkManager, pos: IVec3, old: &Voxel) -> HashSet<IVec3> {
+    let mut changed = HashSet::new();
+
+    if lit_voxel(chunk_manager, pos).is_none() {
+        return changed;
+    }
+
+    for channel in [LightChannel::Sky, LightChannel::Block] {
+        let mut queue = remove(chunk_manager, channel, pos, channel.get(old), &mut changed);
+
+        let is_below_sky = is_below_sky(chunk_manager, pos);
+        let voxel = lit_voxel_mut(chunk_manager, pos).unwrap();
+
+        if !voxel.is_opaque() {
+            if channel == LightChannel::Sky && is_below_sky {
+                voxel.sky_light = MAX_LIGHT;
+                queue.push_back(pos);
+            }
+
+            for direction in DIRECTIONS {
+                queue.push_back(pos + direction);
+            }
+        }
+
+        let voxel = lit_voxel_mut(chunk_manager, pos).unwrap();
+        let emission = voxel.block_type.light_emission();
+        if channel == LightChannel::Block && emission > 0 {
+            voxel.block_light = emission;
+            queue.push_back(pos);
+        }
+
+        propagate(chunk_manager, channel, queue, &mut changed);
+    }
+
+    changed
+}
+
+// Spreads light outwards from the queued voxels into darker, see-through
+// neighbours.
+fn propagate(
+    chunk_manager: &mut ChunkManager,
+    channel: LightChannel,
+    mut queue: VecDeque<IVec3>,
+    changed: &mut HashSet<IVec3>,
+) {
+    while let Some(pos) = queue.pop_front() {
+        let Some(voxel) = lit_voxel(chunk_manager, pos) else {
+            continue;
+        };
+        let level = channel.get(voxel);
+
+        for direction in DIRECTIONS {
+            let neighbour_pos = pos + direction;
+            let spread = channel.spread(level, direction);
+
+            let Some(neighbour) = lit_voxel_mut(chunk_manager, neighbour_pos) else {
+                continue;
+            };
+
+            if neighbour.is_opaque() || channel.get(neighbour) >= spread {
+                continue;
+            }
+
+            channel.set(neighbour, spread);
+            changed.insert(neighbour_pos);
+            queue.push_back(neighbour_pos);
+        }
+    }
+}
+
+// Darkens the voxel at `pos`, which was lit at `level`, along with every
+// voxel that got its light through it. Returns the voxels bordering the
+// darkened area that are lit from elsewhere, to propagate from afterwards.
+fn remove(
+    chunk_manager: &mut ChunkManager,
+    channel: LightChannel,
+    pos: IVec3,
+    level: u8,
+    changed: &mut HashSet<IVec3>,
+) -> VecDeque<IVec3> {
+    let mut relight_queue = VecDeque::new();
+    let mut queue = VecDeque::from([(pos, level)]);
+
+    channel.set(lit_voxel_mut(chunk_manager, pos).unwrap(), 0);
+    changed.insert(pos);
+
+    while let Some((pos, level)) = queue.pop_front() {
+        for direction in DIRECTIONS {
+            let neighbour_pos = pos + direction;
+            let Some(neighbour) = lit_voxel_mut(chunk_manager, neighbour_pos) else {
+                continue;
+            };
+
+            let neighbour_level = channel.get(neighbour);
+            if neighbour_level == 0 {
+                continue;
+            }
+
+            let lit_through_pos = neighbour_level < level
+                || (neighbour_level == level && channel.spread(level, direction) == level);
+
+            if lit_through_pos {
+                channel.set(neighbour, 0);
+                changed.insert(neighbour_pos);
+                queue.push_back((neighbour_pos, neighbour_level));
+            } else {
+                relight_queue.push_back(neighbour_pos);
+            }
+        }
+    }
+
+    relight_queue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::voxel::{BlockType, VoxelData};
+    use std::collections::HashMap;
+
+    // A single empty chunk at the origin with nothing loaded around it, so
+    // the sky is above it and its other faces act as walls.
+    fn empty_chunk_manager() -> ChunkManager {
+        let mut voxels = HashMap::new();
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    voxels.insert(IVec3::new(x, y, z), Voxel::default());
+                }
+            }
+        }
+
+        let mut chunk_manager = ChunkManager::default();
+        chunk_manager.insert_chunk(Chunk {
+            voxel_data: VoxelData { voxels },
+            position: IVec3::ZERO,
+            lod: 0,
+        });
+        light_chunk(&mut chunk_manager, IVec3::ZERO);
+
+        chunk_manager
+    }
+
+    fn block(block_type: BlockType) -> Voxel {
+        Voxel {
+            is_active: true,
+            block_type,
+            ..default()
+        }
+    }
+
+    fn light_at(chunk_manager: &ChunkManager, channel: LightChannel, pos: IVec3) -> u8 {
+        channel.get(chunk_manager.get_voxel_at_global_position(pos).unwrap())
+    }
+
+    #[test]
+    fn sky_light_falls_straight_down() {
+        let mut chunk_manager = empty_chunk_manager();
+        chunk_manager.set_voxel(IVec3::new(3, 0, 3), block(BlockType::Rock));
+
+        for y in 1..CHUNK_SIZE {
+            let pos = IVec3::new(3, y, 3);
+            assert_eq!(light_at(&chunk_manager, LightChannel::Sky, pos), MAX_LIGHT);
+        }
+        let ground = IVec3::new(3, 0, 3);
+        assert_eq!(light_at(&chunk_manager, LightChannel::Sky, ground), 0);
+    }
+
+    #[test]
+    fn lamp_light_fades_with_distance() {
+        let mut chunk_manager = empty_chunk_manager();
+        let lamp = IVec3::new(3, 4, 4);
+        chunk_manager.set_voxel(lamp, block(BlockType::Lamp));
+
+        let emission = BlockType::Lamp.light_emission();
+        for distance in 0..=4 {
+            let pos = lamp + IVec3::X * distance;
+            assert_eq!(
+                light_at(&chunk_manager, LightChannel::Block, pos),
+                emission - distance as u8
+            );
+        }
+    }
+
+    #[test]
+    fn removing_lamp_darkens_neighbours() {
+        let mut chunk_manager = empty_chunk_manager();
+        let lamp = IVec3::new(3, 4, 4);
+        chunk_manager.set_voxel(lamp, block(BlockType::Lamp));
+        chunk_manager.set_voxel(lamp, Voxel::default());
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let pos = IVec3::new(x, y, z);
+                    assert_eq!(light_at(&chunk_manager, LightChannel::Block, pos), 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn removing_wall_relights_gap_from_both_sides() {
+        let mut chunk_manager = empty_chunk_manager();
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk_manager.set_voxel(IVec3::new(4, y, z), block(BlockType::Rock));
+            }
+        }
+
+        let left_lamp = IVec3::new(1, 4, 4);
+        let right_lamp = IVec3::new(7, 4, 4);
+        chunk_manager.set_voxel(left_lamp, block(BlockType::Lamp));
+        chunk_manager.set_voxel(right_lamp, block(BlockType::Lamp));
+
+        let gap = IVec3::new(4, 4, 4);
+        chunk_manager.set_voxel(gap, Voxel::default());
+
+        let emission = BlockType::Lamp.light_emission();
+        assert_eq!(
+            light_at(&chunk_manager, LightChannel::Block, gap),
+            emission - 3
+        );
+
+        // Each lamp now lights the far side of the wall through the gap.
+        chunk_manager.set_voxel(right_lamp, Voxel::default());
+        let beyond_gap = gap + IVec3::X;
+        assert_eq!(
+            light_at(&chunk_manager, LightChannel::Block, beyond_gap),
+            emission - 4
+        );
+
+        chunk_manager.set_voxel(right_lamp, block(BlockType::Lamp));
+        chunk_manager.set_voxel(left_lamp, Voxel::default());
+        let before_gap = gap - IVec3::X;
+        assert_eq!(
+            light_at(&chunk_manager, LightChannel::Block, before_gap),
+            emission - 4
+        );
+    }
+
+    #[test]
+    fn roof_darkens_voxels_below() {
+        let mut chunk_manager = empty_chunk_manager();
+        let below = IVec3::new(3, 2, 3);
+        assert_eq!(
+            light_at(&chunk_manager, LightChannel::Sky, below),
+            MAX_LIGHT
+        );
+
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk_manager.set_voxel(IVec3::new(x, 6, z), block(BlockType::Rock));
+            }
+        }
+
+        assert_eq!(light_at(&chunk_manager, LightChannel::Sky, below), 0);
+        let above = IVec3::new(3, 7, 3);
+        assert_eq!(
+            light_at(&chunk_manager, LightChannel::Sky, above),
+            MAX_LIGHT
+        );
+    }
+}
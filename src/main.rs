@@ -1,30 +1,37 @@
 use bevy::prelude::*;
 use bevy_flycam::prelude::*;
-use voxel_engine::chunk_manager::plugin::ChunkManagerPlugin;
+use voxel_engine::chunk_manager::plugin::{ChunkLoader, ChunkManagerPlugin};
+use voxel_engine::chunk_manager::recorder::ChunkTimingRecorderPlugin;
+use voxel_engine::debug::DebugOverlayPlugin;
 use voxel_engine::diagnostic::ScreenDiagnosticsPlugin;
+use voxel_engine::sky::SkyPlugin;
+
+// Set to a .csv or .jsonl file to record how long each chunk takes to load.
+const CHUNK_TIMINGS_VAR: &str = "CHUNK_TIMINGS";
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
         .add_plugin(PlayerPlugin)
-        .add_plugin(ScreenDiagnosticsPlugin)
+        .add_plugin(ScreenDiagnosticsPlugin::<FlyCam>::default())
         .add_plugin(ChunkManagerPlugin)
-        .add_startup_system(setup)
+        .add_plugin(SkyPlugin)
+        .add_plugin(DebugOverlayPlugin)
+        .add_system(load_chunks_around_camera)
         .insert_resource(MovementSettings {
             speed: 12.0,
             ..default()
-        })
-        .run();
+        });
+
+    if let Some(path) = std::env::var_os(CHUNK_TIMINGS_VAR) {
+        app.add_plugin(ChunkTimingRecorderPlugin::new(path));
+    }
+
+    app.run();
 }
 
-fn setup(mut commands: Commands) {
-    commands.spawn(PointLightBundle {
-        point_light: PointLight {
-            intensity: 1500.0,
-            shadows_enabled: true,
-            ..default()
-        },
-        transform: Transform::from_xyz(0.0, 40.0, 0.0),
-        ..default()
-    });
+fn load_chunks_around_camera(mut commands: Commands, cameras: Query<Entity, Added<FlyCam>>) {
+    for id in &cameras {
+        commands.entity(id).insert(ChunkLoader::default());
+    }
 }
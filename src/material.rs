@@ -0,0 +1,302 @@
+use crate::voxel::RenderLayer;
+use bevy::asset::LoadState;
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::mesh::{MeshVertexAttribute, MeshVertexBufferLayout};
+use bevy::render::render_resource::{
+    AddressMode, AsBindGroup, Extent3d, FilterMode, RenderPipelineDescriptor, SamplerDescriptor,
+    ShaderRef, SpecializedMeshPipelineError, TextureDimension, TextureFormat, VertexFormat,
+};
+use bevy::render::texture::ImageSampler;
+use std::collections::HashMap;
+
+// Number of layers stacked vertically in the terrain texture.
+const TEXTURE_LAYERS: u32 = 4;
+
+/// Per-vertex weight of each terrain texture layer, see
+/// `BlockType::texture_layer`.
+pub const ATTRIBUTE_BLOCK_WEIGHTS: MeshVertexAttribute =
+    MeshVertexAttribute::new("BlockWeights", 988540917, VertexFormat::Float32x4);
+
+/// Per-vertex ambient occlusion, from 0 (fully occluded) to 1.
+pub const ATTRIBUTE_AMBIENT_OCCLUSION: MeshVertexAttribute =
+    MeshVertexAttribute::new("AmbientOcclusion", 988540918, VertexFormat::Float32);
+/// Per-vertex sky and block light, from 0 to 1.
+pub const ATTRIBUTE_LIGHT: MeshVertexAttribute =
+    MeshVertexAttribute::new("Light", 988540919, VertexFormat::Float32x2);
+
+pub struct TerrainMaterialPlugin;
+
+impl Plugin for TerrainMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(MaterialPlugin::<TerrainMaterial>::default())
+            .add_startup_system(load_terrain_texture)
+            .add_system(create_terrain_material);
+    }
+}
+
+#[derive(AsBindGroup, Debug, Clone, TypeUuid)]
+#[uuid = "4c1f6d2e-8a0b-4f7e-9d43-2b5e7a9c1d80"]
+pub struct TerrainMaterial {
+    // Texture repeats per world unit.
+    #[uniform(0)]
+    pub texture_scale: f32,
+    // Brightness of the sky light, set by the time of day.
+    #[uniform(0)]
+    pub sky_light: f32,
+    // Chunks fade in over `fade_duration` seconds from `fade_start`, measured
+    // by `Time::elapsed_seconds_wrapped`. A duration of 0 disables fading.
+    // Once the elapsed time wraps around, it falls before `fade_start` and
+    // the fade counts as finished.
+    #[uniform(0)]
+    pub fade_start: f32,
+    #[uniform(0)]
+    pub fade_duration: f32,
+    // Alpha of translucent surfaces, and the alpha below which alpha-tested
+    // surfaces are cut out. Both follow `alpha_mode`.
+    #[uniform(0)]
+    pub opacity: f32,
+    #[uniform(0)]
+    pub alpha_cutoff: f32,
+    #[texture(1, dimension = "2d_array")]
+    #[sampler(2)]
+    pub texture: Handle<Image>,
+    pub alpha_mode: AlphaMode,
+}
+
+impl TerrainMaterial {
+    /// Opaque material for `texture` that doesn't fade.
+    pub fn new(texture: Handle<Image>) -> Self {
+        Self {
+            texture_scale: 0.25,
+            sky_light: 1.0,
+            fade_start: 0.0,
+            fade_duration: 0.0,
+            opacity: 1.0,
+            alpha_cutoff: 0.0,
+            texture,
+            alpha_mode: AlphaMode::Opaque,
+        }
+    }
+
+    /// Sets the alpha mode, along with the uniforms the shader reads it from.
+    pub fn set_alpha_mode(&mut self, alpha_mode: AlphaMode) {
+        self.alpha_mode = alpha_mode;
+        (self.opacity, self.alpha_cutoff) = match alpha_mode {
+            AlphaMode::Mask(alpha_cutoff) => (1.0, alpha_cutoff),
+            AlphaMode::Blend => (TRANSLUCENT_OPACITY, 0.0),
+            _ => (1.0, 0.0),
+        };
+    }
+}
+
+impl Material for TerrainMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/terrain.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/terrain.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            ATTRIBUTE_BLOCK_WEIGHTS.at_shader_location(2),
+            ATTRIBUTE_AMBIENT_OCCLUSION.at_shader_location(3),
+            ATTRIBUTE_LIGHT.at_shader_location(4),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+
+        Ok(())
+    }
+}
+
+// Translucent blocks don't have textures with alpha yet, so they're all
+// equally see-through.
+const TRANSLUCENT_OPACITY: f32 = 0.6;
+const FOLIAGE_ALPHA_CUTOFF: f32 = 0.5;
+
+/// Alpha mode the surfaces of `layer` are drawn with.
+pub fn layer_alpha_mode(layer: RenderLayer) -> AlphaMode {
+    match layer {
+        RenderLayer::Opaque => AlphaMode::Opaque,
+        RenderLayer::Foliage => AlphaMode::Mask(FOLIAGE_ALPHA_CUTOFF),
+        RenderLayer::Translucent => AlphaMode::Blend,
+    }
+}
+
+/// Seconds over which a newly spawned chunk fades in.
+pub const CHUNK_FADE_DURATION: f32 = 1.0;
+// Chunks spawned within this many seconds of each other share a material
+// while they fade in.
+const FADE_GROUP_INTERVAL: f32 = 0.1;
+
+/// Identifies one of the materials shared between chunk meshes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TerrainMaterialKey {
+    pub layer: RenderLayer,
+    /// Group of chunks fading in together, or `None` once they've faded in.
+    pub fade_group: Option<u32>,
+}
+
+impl TerrainMaterialKey {
+    /// Key for the surfaces of `layer` in chunks that have faded in.
+    pub fn new(layer: RenderLayer) -> Self {
+        Self {
+            layer,
+            fade_group: None,
+        }
+    }
+
+    /// Key for the surfaces of `layer` in a chunk starting to fade in at
+    /// `elapsed_seconds_wrapped`.
+    pub fn fading(layer: RenderLayer, elapsed_seconds_wrapped: f32) -> Self {
+        Self {
+            layer,
+            fade_group: Some((elapsed_seconds_wrapped / FADE_GROUP_INTERVAL) as u32),
+        }
+    }
+
+    /// Whether the chunks using this material have finished fading in at
+    /// `elapsed_seconds_wrapped`.
+    // `Option::is_none_or` needs a newer Rust than Bevy 0.10 does.
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn has_faded_in(&self, elapsed_seconds_wrapped: f32) -> bool {
+        self.fade_group.map_or(true, |group| {
+            let elapsed = elapsed_seconds_wrapped - group as f32 * FADE_GROUP_INTERVAL;
+            // See `TerrainMaterial::fade_start` for wrapping.
+            !(0.0..CHUNK_FADE_DURATION + FADE_GROUP_INTERVAL).contains(&elapsed)
+        })
+    }
+}
+
+/// Materials shared between chunk meshes, created from a common template on
+/// first use. Inserted once the terrain texture has loaded.
+#[derive(Resource)]
+pub struct TerrainMaterials {
+    template: TerrainMaterial,
+    handles: HashMap<TerrainMaterialKey, Handle<TerrainMaterial>>,
+}
+
+impl TerrainMaterials {
+    pub fn new(template: TerrainMaterial) -> Self {
+        Self {
+            template,
+            handles: HashMap::new(),
+        }
+    }
+
+    pub fn get_or_create(
+        &mut self,
+        key: TerrainMaterialKey,
+        materials: &mut Assets<TerrainMaterial>,
+    ) -> Handle<TerrainMaterial> {
+        let template = &self.template;
+
+        self.handles
+            .entry(key)
+            .or_insert_with(|| {
+                let mut material = template.clone();
+                material.set_alpha_mode(layer_alpha_mode(key.layer));
+                if let Some(group) = key.fade_group {
+                    material.fade_start = group as f32 * FADE_GROUP_INTERVAL;
+                    material.fade_duration = CHUNK_FADE_DURATION;
+                }
+
+                materials.add(material)
+            })
+            .clone()
+    }
+
+    /// Applies `update` to the template and every material made from it.
+    pub fn update(
+        &mut self,
+        materials: &mut Assets<TerrainMaterial>,
+        update: impl Fn(&mut TerrainMaterial),
+    ) {
+        update(&mut self.template);
+
+        for handle in self.handles.values() {
+            if let Some(material) = materials.get_mut(handle) {
+                update(material);
+            }
+        }
+    }
+
+    /// Drops the cached materials for which `keep` returns false. Meshes still
+    /// using them keep them alive until they switch material.
+    pub fn retain(&mut self, mut keep: impl FnMut(&TerrainMaterialKey) -> bool) {
+        self.handles.retain(|key, _| keep(key));
+    }
+}
+
+#[derive(Resource)]
+struct LoadingTerrainTexture(Handle<Image>);
+
+fn load_terrain_texture(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let texture = asset_server.load("textures/terrain.png");
+    commands.insert_resource(LoadingTerrainTexture(texture));
+}
+
+// The texture can only be bound as an array once it has loaded and its layers
+// have been split, so the materials aren't created before then. If it fails
+// to load, chunks are drawn with a plain white texture instead.
+fn create_terrain_material(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    loading_texture: Option<Res<LoadingTerrainTexture>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(loading_texture) = loading_texture else {
+        return;
+    };
+
+    let texture = match asset_server.get_load_state(&loading_texture.0) {
+        LoadState::Loaded => {
+            let image = images.get_mut(&loading_texture.0).unwrap();
+            image.reinterpret_stacked_2d_as_array(TEXTURE_LAYERS);
+            image.sampler_descriptor = ImageSampler::Descriptor(SamplerDescriptor {
+                address_mode_u: AddressMode::Repeat,
+                address_mode_v: AddressMode::Repeat,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..default()
+            });
+            loading_texture.0.clone()
+        }
+        LoadState::Failed => {
+            error!("Failed to load the terrain texture, using a placeholder");
+            images.add(placeholder_texture())
+        }
+        _ => return,
+    };
+
+    commands.insert_resource(TerrainMaterials::new(TerrainMaterial::new(texture)));
+    commands.remove_resource::<LoadingTerrainTexture>();
+}
+
+// A white 1x1 texture array with as many layers as the terrain texture.
+fn placeholder_texture() -> Image {
+    Image::new_fill(
+        Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: TEXTURE_LAYERS,
+        },
+        TextureDimension::D2,
+        &[255; 4],
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
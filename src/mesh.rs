@@ -1,107 +1,646 @@
-use crate::chunk::{Chunk, CHUNK_SIZE};
+use crate::chunk::CHUNK_SIZE;
+use crate::chunk_manager::culling::ChunkConnectivity;
 use crate::chunk_manager::ChunkManager;
+use crate::light::MAX_LIGHT;
+use crate::material::{ATTRIBUTE_AMBIENT_OCCLUSION, ATTRIBUTE_BLOCK_WEIGHTS, ATTRIBUTE_LIGHT};
 use crate::tables::TRIANGULATION;
+use crate::voxel::{RenderLayer, Voxel};
+use bevy::log::info_span;
 use bevy::prelude::*;
 use bevy::render::{mesh::Indices, render_resource::PrimitiveTopology};
+use std::collections::{HashMap, HashSet};
 
+const AXES: [IVec3; 3] = [IVec3::X, IVec3::Y, IVec3::Z];
+
+/// Algorithm used to turn chunk voxels into a mesh.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mesher {
+    #[default]
+    MarchingCubes,
+    SurfaceNets,
+}
+
+/// How normals are assigned to chunk meshes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Shading {
+    /// Per-vertex normals from the voxel density gradient.
+    #[default]
+    Smooth,
+    /// One normal per triangle, giving a faceted look.
+    Flat,
+}
+
+// How much fully enclosed vertices are darkened by ambient occlusion.
+const AMBIENT_OCCLUSION_STRENGTH: f32 = 0.8;
+
+/// Changing the settings meshes all loaded chunks again.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct MeshSettings {
+    pub mesher: Mesher,
+    pub shading: Shading,
+    pub ambient_occlusion: bool,
+}
+
+impl Default for MeshSettings {
+    fn default() -> Self {
+        Self {
+            mesher: Mesher::default(),
+            shading: Shading::default(),
+            ambient_occlusion: true,
+        }
+    }
+}
+
+/// Snapshot of a chunk's voxels plus a two-voxel apron copied from its
+/// neighbours, so the chunk can be meshed without access to the
+/// `ChunkManager`, e.g. on another thread.
+///
+/// Positions are in the chunk's own voxel grid, which is coarser than the
+/// world grid at higher levels of detail.
 #[derive(Debug, Clone)]
+pub struct ChunkMeshInput {
+    pub position: IVec3,
+    pub lod: u32,
+    // Set when a neighbour has a different level of detail, so the mesh
+    // needs skirts to hide the cracks along the seam.
+    pub skirts: bool,
+    voxels: Vec<Voxel>,
+}
+
+impl ChunkMeshInput {
+    // Cube corners go from 0 to the chunk size, surface nets cells start one
+    // voxel before, and normals and ambient occlusion sample one voxel
+    // further out.
+    const APRON: i32 = 2;
+    const MIN: i32 = -Self::APRON;
+
+    /// Copies the voxels around the chunk at `position`, or returns `None`
+    /// if the chunk or any of its neighbours isn't loaded yet.
+    pub fn new(position: IVec3, chunk_manager: &ChunkManager) -> Option<Self> {
+        if !chunk_manager.neighbours_loaded(position) {
+            return None;
+        }
+
+        let _span = info_span!("copy_mesh_input", ?position).entered();
+
+        let lod = chunk_manager.get_chunk(position)?.lod;
+        let skirts = (-1..=1).any(|x| {
+            (-1..=1).any(|z| {
+                let neighbour_position = position + IVec3::new(x, 0, z) * CHUNK_SIZE;
+                chunk_manager
+                    .get_chunk(neighbour_position)
+                    .is_some_and(|neighbour| neighbour.lod != lod)
+            })
+        });
+
+        let mut input = Self {
+            position,
+            lod,
+            skirts,
+            voxels: Vec::new(),
+        };
+
+        let max = input.size() + Self::APRON;
+        input.voxels.reserve(input.padded_size().pow(3) as usize);
+
+        for z in Self::MIN..=max {
+            for y in Self::MIN..=max {
+                for x in Self::MIN..=max {
+                    // Voxels above and below the world are empty and open to
+                    // the sky.
+                    let global_position = position + IVec3::new(x, y, z) * input.scale();
+                    let voxel = chunk_manager
+                        .get_voxel_at_global_position(global_position)
+                        .cloned()
+                        .unwrap_or(Voxel {
+                            sky_light: MAX_LIGHT,
+                            ..default()
+                        });
+
+                    input.voxels.push(voxel);
+                }
+            }
+        }
+
+        Some(input)
+    }
+
+    /// Number of voxels along each axis, excluding the apron.
+    pub fn size(&self) -> i32 {
+        CHUNK_SIZE >> self.lod
+    }
+
+    /// Width of a voxel in world units.
+    pub fn scale(&self) -> i32 {
+        1 << self.lod
+    }
+
+    fn padded_size(&self) -> i32 {
+        self.size() + 2 * Self::APRON + 1
+    }
+
+    /// Returns the voxel at `pos` relative to the chunk, which must lie
+    /// within the apron.
+    pub fn get(&self, pos: IVec3) -> &Voxel {
+        let padded_size = self.padded_size();
+        let pos = pos - Self::MIN;
+        let index = pos.x + pos.y * padded_size + pos.z * padded_size * padded_size;
+
+        &self.voxels[index as usize]
+    }
+
+    pub fn is_opaque(&self, pos: IVec3) -> bool {
+        self.get(pos).is_opaque()
+    }
+
+    /// Whether the voxel at `pos` is part of the surface of `layer`. Voxels
+    /// of other layers count as empty, so each layer is closed off where it
+    /// meets another.
+    pub fn is_solid(&self, pos: IVec3, layer: RenderLayer) -> bool {
+        let voxel = self.get(pos);
+        voxel.is_active && voxel.block_type.render_layer() == layer
+    }
+
+    /// Whether any voxel belongs to `layer`, apron included.
+    pub fn has_layer(&self, layer: RenderLayer) -> bool {
+        self.voxels
+            .iter()
+            .any(|voxel| voxel.is_active && voxel.block_type.render_layer() == layer)
+    }
+
+    // Converts a position on the chunk's voxel grid to world space.
+    fn to_world(&self, pos: Vec3) -> Vec3 {
+        pos * self.scale() as f32 + self.position.as_vec3()
+    }
+
+    fn to_grid(&self, pos: Vec3) -> Vec3 {
+        (pos - self.position.as_vec3()) / self.scale() as f32
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct MeshData {
     pub vertices: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    // Weights of the terrain texture layers, blended across each triangle.
+    pub block_weights: Vec<[f32; 4]>,
+    // Ambient light reaching each vertex, from 0 (fully occluded) to 1.
+    pub ambient_occlusion: Vec<f32>,
+    // Sky and block light reaching each vertex, from 0 to 1.
+    pub light: Vec<[f32; 2]>,
     pub indices: Vec<u32>,
 }
 
+/// Meshes of a chunk's render layers, leaving out layers without any
+/// geometry.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkMeshData {
+    pub layers: Vec<(RenderLayer, MeshData)>,
+    pub connectivity: ChunkConnectivity,
+}
+
+impl ChunkMeshData {
+    pub fn generate(mesh_settings: MeshSettings, input: &ChunkMeshInput) -> Self {
+        let _span = info_span!("mesh_chunk", position = ?input.position, lod = input.lod).entered();
+
+        let layers = RenderLayer::ALL
+            .into_iter()
+            .filter(|layer| input.has_layer(*layer))
+            .map(|layer| (layer, MeshData::generate(mesh_settings, input, layer)))
+            .filter(|(_, mesh_data)| !mesh_data.indices.is_empty())
+            .collect();
+
+        let connectivity =
+            info_span!("chunk_connectivity").in_scope(|| ChunkConnectivity::new(input));
+
+        Self {
+            layers,
+            connectivity,
+        }
+    }
+}
+
+// Attributes of a single vertex, in world space.
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    position: Vec3,
+    normal: Vec3,
+    block_weights: [f32; 4],
+    ambient_occlusion: f32,
+    light: [f32; 2],
+}
+
 impl MeshData {
-    pub fn create_mesh(&self) -> Mesh {
+    pub fn create_mesh(&self, shading: Shading) -> Mesh {
         let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
 
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.vertices.clone());
+        mesh.insert_attribute(ATTRIBUTE_BLOCK_WEIGHTS, self.block_weights.clone());
+        mesh.insert_attribute(ATTRIBUTE_AMBIENT_OCCLUSION, self.ambient_occlusion.clone());
+        mesh.insert_attribute(ATTRIBUTE_LIGHT, self.light.clone());
         mesh.set_indices(Some(Indices::U32(self.indices.clone())));
-        mesh.duplicate_vertices();
-        mesh.compute_flat_normals();
+
+        match shading {
+            Shading::Smooth => {
+                mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals.clone());
+            }
+            Shading::Flat => {
+                mesh.duplicate_vertices();
+                mesh.compute_flat_normals();
+            }
+        }
 
         mesh
     }
 
-    pub fn generate_marching_cubes(chunk: &Chunk, chunk_manager: &ChunkManager) -> Self {
-        let mut vertices = Vec::new();
-        let mut indices = Vec::new();
+    /// Meshes the surface of the voxels in `layer`.
+    pub fn generate(
+        mesh_settings: MeshSettings,
+        input: &ChunkMeshInput,
+        layer: RenderLayer,
+    ) -> Self {
+        let _span = info_span!("mesh_layer", ?layer).entered();
+
+        let mut mesh_data = match mesh_settings.mesher {
+            Mesher::MarchingCubes => info_span!("marching_cubes")
+                .in_scope(|| Self::generate_marching_cubes(input, layer)),
+            Mesher::SurfaceNets => {
+                info_span!("surface_nets").in_scope(|| Self::generate_surface_nets(input, layer))
+            }
+        };
+
+        if mesh_settings.ambient_occlusion {
+            info_span!("bake_ambient_occlusion")
+                .in_scope(|| mesh_data.bake_ambient_occlusion(input));
+        }
+
+        info_span!("bake_light").in_scope(|| mesh_data.bake_light(input));
+
+        if input.skirts {
+            info_span!("add_skirts").in_scope(|| mesh_data.add_skirts(input.scale() as f32));
+        }
+
+        mesh_data
+    }
+
+    // Darkens each vertex by how opaque the space one voxel out along its
+    // normal is, so creases and overhangs receive less ambient light.
+    pub fn bake_ambient_occlusion(&mut self, input: &ChunkMeshInput) {
+        for (i, ambient_occlusion) in self.ambient_occlusion.iter_mut().enumerate() {
+            let position = input.to_grid(Vec3::from(self.vertices[i]));
+            let sample = position + Vec3::from(self.normals[i]);
+
+            let corner = sample.floor();
+            let t = sample - corner;
+            let corner = corner.as_ivec3();
+
+            let mut density = 0.0;
+            for j in 0..8 {
+                let offset = cell_corner_offset(j);
+                if input.is_opaque(corner + offset) {
+                    let weight = Vec3::select(offset.cmpeq(IVec3::ONE), t, Vec3::ONE - t);
+                    density += weight.x * weight.y * weight.z;
+                }
+            }
+
+            *ambient_occlusion = 1.0 - AMBIENT_OCCLUSION_STRENGTH * density;
+        }
+    }
+
+    // Takes the light of the see-through space one voxel out along each
+    // vertex's normal, blending the surrounding voxels that light can reach.
+    pub fn bake_light(&mut self, input: &ChunkMeshInput) {
+        for (i, light) in self.light.iter_mut().enumerate() {
+            let position = input.to_grid(Vec3::from(self.vertices[i]));
+            let sample = position + Vec3::from(self.normals[i]);
+
+            let corner = sample.floor();
+            let t = sample - corner;
+            let corner = corner.as_ivec3();
 
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                for z in 0..CHUNK_SIZE {
+            let mut total = Vec2::ZERO;
+            let mut total_weight = 0.0;
+            for j in 0..8 {
+                let offset = cell_corner_offset(j);
+                let voxel = input.get(corner + offset);
+                if voxel.is_opaque() {
+                    continue;
+                }
+
+                let weight = Vec3::select(offset.cmpeq(IVec3::ONE), t, Vec3::ONE - t);
+                let weight = weight.x * weight.y * weight.z;
+                total += Vec2::new(voxel.sky_light as f32, voxel.block_light as f32) * weight;
+                total_weight += weight;
+            }
+
+            if total_weight > 0.0 {
+                *light = (total / (total_weight * MAX_LIGHT as f32)).to_array();
+            }
+        }
+    }
+
+    // Hangs a strip of triangles down from every open edge of the mesh, which
+    // only occur along the chunk's borders. Where a neighbour is meshed at a
+    // different level of detail the strips cover the gaps between the two
+    // surfaces.
+    pub fn add_skirts(&mut self, depth: f32) {
+        let mut edges = HashSet::new();
+        for triangle in self.indices.chunks(3) {
+            for i in 0..3 {
+                edges.insert((triangle[i], triangle[(i + 1) % 3]));
+            }
+        }
+
+        let mut skirt_vertices = HashMap::new();
+        let mut skirt_indices = Vec::new();
+
+        for triangle in self.indices.clone().chunks(3) {
+            for i in 0..3 {
+                let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                if edges.contains(&(b, a)) {
+                    continue;
+                }
+
+                let [lower_a, lower_b] = [a, b].map(|index| {
+                    *skirt_vertices.entry(index).or_insert_with(|| {
+                        let mut vertex = self.vertex(index);
+                        vertex.position.y -= depth;
+                        self.push_vertex(vertex)
+                    })
+                });
+
+                skirt_indices.extend([b, a, lower_a, b, lower_a, lower_b]);
+            }
+        }
+
+        self.indices.extend(skirt_indices);
+    }
+
+    fn push_vertex(&mut self, vertex: Vertex) -> u32 {
+        self.vertices.push(vertex.position.to_array());
+        self.normals.push(vertex.normal.to_array());
+        self.block_weights.push(vertex.block_weights);
+        self.ambient_occlusion.push(vertex.ambient_occlusion);
+        self.light.push(vertex.light);
+
+        self.vertices.len() as u32 - 1
+    }
+
+    fn vertex(&self, index: u32) -> Vertex {
+        let index = index as usize;
+
+        Vertex {
+            position: Vec3::from(self.vertices[index]),
+            normal: Vec3::from(self.normals[index]),
+            block_weights: self.block_weights[index],
+            ambient_occlusion: self.ambient_occlusion[index],
+            light: self.light[index],
+        }
+    }
+
+    pub fn generate_marching_cubes(input: &ChunkMeshInput, layer: RenderLayer) -> Self {
+        let is_active = |pos: IVec3| input.is_solid(pos, layer);
+
+        let mut mesh_data = Self::default();
+        // Edge vertices keyed by the sum of the edge's corner positions, which
+        // is unique per edge, so neighbouring cells share them.
+        let mut edge_vertices = HashMap::new();
+
+        for x in 0..input.size() {
+            for y in 0..input.size() {
+                for z in 0..input.size() {
                     let mut case = 0;
                     let relative_voxel_position = IVec3::new(x, y, z);
-                    let global_voxel_position = relative_voxel_position + chunk.position;
 
-                    let cube_vertices = generate_cube_vertices(global_voxel_position.as_vec3());
+                    let cube_vertices = generate_cube_vertices(relative_voxel_position);
 
                     for (i, vertex) in cube_vertices.iter().enumerate() {
-                        let pos = IVec3::new(vertex[0] as i32, vertex[1] as i32, vertex[2] as i32);
-
-                        if let Some(voxel) = chunk_manager.get_voxel_at_global_position(pos) {
-                            if voxel.is_active {
-                                case |= 1 << i;
-                            }
+                        if is_active(*vertex) {
+                            case |= 1 << i;
                         }
                     }
 
                     // lookup case in table to get triangles
-                    let triangles = TRIANGULATION[case]
-                        .clone()
-                        .iter()
-                        .filter(|i| **i != -1)
-                        .map(|i| *i as u32 + vertices.len() as u32)
-                        .collect::<Vec<_>>();
-
-                    vertices.extend(generate_cube_edges(global_voxel_position.as_vec3()));
-                    indices.extend(triangles);
+                    for edge in TRIANGULATION[case].iter().take_while(|i| **i != -1) {
+                        let (a, b) = CUBE_EDGES[*edge as usize];
+                        let (a, b) = (cube_vertices[a], cube_vertices[b]);
+
+                        let index = *edge_vertices.entry(a + b).or_insert_with(|| {
+                            // The surface takes the texture of the solid side.
+                            let active_corner = if is_active(a) { a } else { b };
+
+                            mesh_data.push_vertex(Vertex {
+                                position: input.to_world((a + b).as_vec3() * 0.5),
+                                normal: edge_normal(a, b, is_active),
+                                block_weights: block_weights(input.get(active_corner)),
+                                ambient_occlusion: 1.0,
+                                light: [0.0; 2],
+                            })
+                        });
+
+                        mesh_data.indices.push(index);
+                    }
+                }
+            }
+        }
+
+        mesh_data
+    }
+
+    // Surface nets places one vertex inside every cell the surface passes
+    // through and connects the vertices of the four cells around each edge
+    // with a sign change into a quad, so vertices are shared between faces.
+    pub fn generate_surface_nets(input: &ChunkMeshInput, layer: RenderLayer) -> Self {
+        let is_active = |pos: IVec3| input.is_solid(pos, layer);
+
+        let mut mesh_data = Self::default();
+        let mut cell_vertices = HashMap::new();
+
+        // Cells start one voxel outside the chunk so that the quads on the
+        // chunk's lower faces can be closed.
+        for x in -1..input.size() {
+            for y in -1..input.size() {
+                for z in -1..input.size() {
+                    let cell = IVec3::new(x, y, z);
+
+                    if let Some(vertex) = generate_cell_vertex(cell, input, layer) {
+                        cell_vertices.insert(cell, mesh_data.push_vertex(vertex));
+                    }
                 }
             }
         }
 
-        Self { vertices, indices }
+        for x in 0..input.size() {
+            for y in 0..input.size() {
+                for z in 0..input.size() {
+                    let voxel_position = IVec3::new(x, y, z);
+                    let active = is_active(voxel_position);
+
+                    for (i, axis) in AXES.iter().enumerate() {
+                        if active == is_active(voxel_position + *axis) {
+                            continue;
+                        }
+
+                        // The four cells sharing the edge, counter-clockwise
+                        // when looking down the axis.
+                        let u = AXES[(i + 1) % 3];
+                        let v = AXES[(i + 2) % 3];
+                        let quad = [
+                            voxel_position - u - v,
+                            voxel_position - v,
+                            voxel_position,
+                            voxel_position - u,
+                        ]
+                        .map(|cell| cell_vertices[&cell]);
+
+                        // Faces point away from the active voxel.
+                        if active {
+                            mesh_data
+                                .indices
+                                .extend([quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+                        } else {
+                            mesh_data
+                                .indices
+                                .extend([quad[0], quad[2], quad[1], quad[0], quad[3], quad[2]]);
+                        }
+                    }
+                }
+            }
+        }
+
+        mesh_data
     }
 }
 
-fn generate_cube_vertices(pos: Vec3) -> Vec<[f32; 3]> {
-    let x = pos.x;
-    let y = pos.y;
-    let z = pos.z;
+// Returns the vertex of a surface nets cell, or `None` if the surface doesn't
+// pass through it. The vertex is the average of the edge crossings, the
+// normal points down the density gradient and the texture is blended from
+// the cell's active voxels.
+fn generate_cell_vertex(cell: IVec3, input: &ChunkMeshInput, layer: RenderLayer) -> Option<Vertex> {
+    let corners = (0..8)
+        .map(|i| cell + cell_corner_offset(i))
+        .filter(|pos| input.is_solid(*pos, layer))
+        .map(|pos| input.get(pos))
+        .collect::<Vec<_>>();
+    let density = (0..8)
+        .map(|i| {
+            if input.is_solid(cell + cell_corner_offset(i), layer) {
+                1.0
+            } else {
+                0.0
+            }
+        })
+        .collect::<Vec<f32>>();
 
-    // 8 points of cube
-    vec![
-        [x + 0.0, y + 0.0, z + 0.0],
-        [x + 0.0, y + 1.0, z + 0.0],
-        [x + 1.0, y + 1.0, z + 0.0],
-        [x + 1.0, y + 0.0, z + 0.0],
-        [x + 0.0, y + 0.0, z + 1.0],
-        [x + 0.0, y + 1.0, z + 1.0],
-        [x + 1.0, y + 1.0, z + 1.0],
-        [x + 1.0, y + 0.0, z + 1.0],
-    ]
+    if density.iter().all(|d| *d == density[0]) {
+        return None;
+    }
+
+    let mut crossing_sum = Vec3::ZERO;
+    let mut crossing_count = 0;
+    let mut gradient = Vec3::ZERO;
+
+    // Visit each of the 12 cell edges once, from its lower corner.
+    for i in 0..8 {
+        for (bit, axis) in AXES.iter().enumerate() {
+            if i & 1 << bit != 0 {
+                continue;
+            }
+
+            let j = i | 1 << bit;
+            gradient += axis.as_vec3() * (density[j] - density[i]);
+
+            if density[i] != density[j] {
+                crossing_sum += cell_corner_offset(i).as_vec3() + axis.as_vec3() * 0.5;
+                crossing_count += 1;
+            }
+        }
+    }
+
+    let mut weights = [0.0; 4];
+    let active_count = corners.len() as f32;
+    for voxel in corners {
+        for (weight, corner_weight) in weights.iter_mut().zip(block_weights(voxel)) {
+            *weight += corner_weight / active_count;
+        }
+    }
+
+    let position = cell.as_vec3() + crossing_sum / crossing_count as f32;
+
+    Some(Vertex {
+        position: input.to_world(position),
+        normal: (-gradient).try_normalize().unwrap_or(Vec3::Y),
+        block_weights: weights,
+        ambient_occlusion: 1.0,
+        light: [0.0; 2],
+    })
 }
 
-fn generate_cube_edges(pos: Vec3) -> Vec<[f32; 3]> {
+// One-hot weights selecting the voxel's texture layer.
+fn block_weights(voxel: &Voxel) -> [f32; 4] {
+    let mut weights = [0.0; 4];
+    weights[voxel.block_type.texture_layer()] = 1.0;
+    weights
+}
+
+fn cell_corner_offset(i: usize) -> IVec3 {
+    IVec3::new(i as i32 & 1, (i as i32 >> 1) & 1, (i as i32 >> 2) & 1)
+}
+
+// Normal of the vertex on the edge between corners `a` and `b`, pointing down
+// the density gradient averaged over both corners. Falls back to the edge
+// direction, pointing from the active corner, when the gradient cancels out.
+fn edge_normal(a: IVec3, b: IVec3, is_active: impl Fn(IVec3) -> bool) -> Vec3 {
+    let density = |pos: IVec3| if is_active(pos) { 1.0 } else { 0.0 };
+    let gradient = |pos: IVec3| {
+        Vec3::new(
+            density(pos + IVec3::X) - density(pos - IVec3::X),
+            density(pos + IVec3::Y) - density(pos - IVec3::Y),
+            density(pos + IVec3::Z) - density(pos - IVec3::Z),
+        )
+    };
+
+    let edge_direction = (b - a).as_vec3() * (density(a) - density(b));
+
+    (-(gradient(a) + gradient(b)))
+        .try_normalize()
+        .unwrap_or(edge_direction)
+}
+
+fn generate_cube_vertices(pos: IVec3) -> [IVec3; 8] {
     let x = pos.x;
     let y = pos.y;
     let z = pos.z;
 
-    vec![
-        [x + 0.0, y + 0.5, z + 0.0],
-        [x + 0.5, y + 1.0, z + 0.0],
-        [x + 1.0, y + 0.5, z + 0.0],
-        [x + 0.5, y + 0.0, z + 0.0],
-        [x + 0.0, y + 0.5, z + 1.0],
-        [x + 0.5, y + 1.0, z + 1.0],
-        [x + 1.0, y + 0.5, z + 1.0],
-        [x + 0.5, y + 0.0, z + 1.0],
-        [x + 0.0, y + 0.0, z + 0.5],
-        [x + 0.0, y + 1.0, z + 0.5],
-        [x + 1.0, y + 1.0, z + 0.5],
-        [x + 1.0, y + 0.0, z + 0.5],
+    // 8 points of cube
+    [
+        IVec3::new(x, y, z),
+        IVec3::new(x, y + 1, z),
+        IVec3::new(x + 1, y + 1, z),
+        IVec3::new(x + 1, y, z),
+        IVec3::new(x, y, z + 1),
+        IVec3::new(x, y + 1, z + 1),
+        IVec3::new(x + 1, y + 1, z + 1),
+        IVec3::new(x + 1, y, z + 1),
     ]
 }
 
+// Pairs of cube vertices joined by each of the 12 edges used in TRIANGULATION.
+const CUBE_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (1, 2),
+    (2, 3),
+    (3, 0),
+    (4, 5),
+    (5, 6),
+    (6, 7),
+    (7, 4),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
 fn _generate_cube_indices(start_index: u32) -> Vec<u32> {
     // indices of points that make up triangles
     vec![
@@ -116,3 +655,134 @@ fn _generate_cube_indices(start_index: u32) -> Vec<u32> {
     .map(|index| index + start_index)
     .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    // Chunks from -1 to `max_x` along x and -1 to 1 along z, so that every
+    // chunk from 0 to `max_x - 1` can be meshed.
+    fn chunk_manager(max_x: i32) -> ChunkManager {
+        let mut chunk_manager = ChunkManager::default();
+        for x in -1..=max_x {
+            for z in -1..=1 {
+                chunk_manager.insert_chunk(Chunk::new(IVec3::new(x, 0, z) * CHUNK_SIZE, 0));
+            }
+        }
+        chunk_manager
+    }
+
+    fn marching_cubes(position: IVec3, chunk_manager: &ChunkManager) -> MeshData {
+        let input = ChunkMeshInput::new(position, chunk_manager).unwrap();
+        MeshData::generate_marching_cubes(&input, RenderLayer::Opaque)
+    }
+
+    fn key(vertex: [f32; 3]) -> [u32; 3] {
+        vertex.map(f32::to_bits)
+    }
+
+    #[test]
+    fn marching_cubes_welds_shared_edge_vertices() {
+        let mesh_data = marching_cubes(IVec3::ZERO, &chunk_manager(1));
+        assert!(!mesh_data.vertices.is_empty());
+
+        let mut seen = HashSet::new();
+        for vertex in &mesh_data.vertices {
+            assert!(seen.insert(key(*vertex)), "duplicate vertex {vertex:?}");
+        }
+    }
+
+    #[test]
+    fn marching_cubes_vertices_match_across_chunk_faces() {
+        let chunk_manager = chunk_manager(2);
+        let left = marching_cubes(IVec3::ZERO, &chunk_manager);
+        let right = marching_cubes(IVec3::X * CHUNK_SIZE, &chunk_manager);
+
+        // Vertices and normals on the face the two chunks share.
+        let face_vertices = |mesh_data: &MeshData| {
+            mesh_data
+                .vertices
+                .iter()
+                .zip(&mesh_data.normals)
+                .filter(|(vertex, _)| vertex[0] == CHUNK_SIZE as f32)
+                .map(|(vertex, normal)| (key(*vertex), key(*normal)))
+                .collect::<HashSet<_>>()
+        };
+
+        let left = face_vertices(&left);
+        assert!(!left.is_empty());
+        assert_eq!(left, face_vertices(&right));
+    }
+
+    #[test]
+    fn mesh_input_matches_chunk_manager() {
+        let chunk_manager = chunk_manager(1);
+        let input = ChunkMeshInput::new(IVec3::ZERO, &chunk_manager).unwrap();
+
+        let max = input.size() + ChunkMeshInput::APRON;
+        for x in ChunkMeshInput::MIN..=max {
+            for y in ChunkMeshInput::MIN..=max {
+                for z in ChunkMeshInput::MIN..=max {
+                    let pos = IVec3::new(x, y, z);
+                    if let Some(voxel) = chunk_manager.get_voxel_at_global_position(pos) {
+                        // Voxels don't implement `PartialEq`.
+                        assert_eq!(format!("{:?}", input.get(pos)), format!("{voxel:?}"));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_next_to_a_different_lod_get_skirts() {
+        let mut chunk_manager = chunk_manager(1);
+        chunk_manager.insert_chunk(Chunk::new(IVec3::ZERO, 1));
+        let input = ChunkMeshInput::new(IVec3::ZERO, &chunk_manager).unwrap();
+        assert!(input.skirts);
+
+        let plain_input = ChunkMeshInput {
+            skirts: false,
+            ..input.clone()
+        };
+        let settings = MeshSettings::default();
+        let plain = MeshData::generate(settings, &plain_input, RenderLayer::Opaque);
+        let skirted = MeshData::generate(settings, &input, RenderLayer::Opaque);
+        assert!(skirted.indices.len() > plain.indices.len());
+
+        // Skirt vertices hang `scale` units below open edges on the border.
+        let size = CHUNK_SIZE as f32;
+        let on_border = |vertex: &[f32; 3]| {
+            [vertex[0], vertex[2]]
+                .iter()
+                .any(|&coordinate| coordinate == 0.0 || coordinate == size)
+        };
+        let border = plain
+            .vertices
+            .iter()
+            .filter(|vertex| on_border(vertex))
+            .map(|vertex| key(*vertex))
+            .collect::<HashSet<_>>();
+        let skirt_vertices = &skirted.vertices[plain.vertices.len()..];
+        assert!(!skirt_vertices.is_empty());
+        for vertex in skirt_vertices {
+            let top = [vertex[0], vertex[1] + input.scale() as f32, vertex[2]];
+            assert!(
+                border.contains(&key(top)),
+                "{vertex:?} isn't below the border"
+            );
+        }
+    }
+
+    #[test]
+    fn chunks_next_to_the_same_lod_get_no_skirts() {
+        let chunk_manager = chunk_manager(1);
+        let input = ChunkMeshInput::new(IVec3::ZERO, &chunk_manager).unwrap();
+        assert!(!input.skirts);
+
+        let mesh_data = MeshData::generate(MeshSettings::default(), &input, RenderLayer::Opaque);
+        let unskirted = MeshData::generate_marching_cubes(&input, RenderLayer::Opaque);
+        assert_eq!(mesh_data.vertices.len(), unskirted.vertices.len());
+        assert_eq!(mesh_data.indices, unskirted.indices);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::chunk::CHUNK_SIZE;
+use crate::chunk_manager::RenderDistance;
+use crate::material::{TerrainMaterial, TerrainMaterials};
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+
+const SUN_ILLUMINANCE: f32 = 10_000.0;
+const MOON_ILLUMINANCE: f32 = 500.0;
+// Angle of the sun's path away from straight overhead, in radians.
+const SUN_TILT: f32 = 0.4;
+// Fraction of the sky light that remains at night.
+const NIGHT_SKY_LIGHT: f32 = 0.15;
+
+const DAY_SKY_COLOR: Vec3 = Vec3::new(0.47, 0.69, 0.96);
+const NIGHT_SKY_COLOR: Vec3 = Vec3::new(0.01, 0.01, 0.04);
+const SUNSET_COLOR: Vec3 = Vec3::new(0.98, 0.55, 0.25);
+const MOON_COLOR: Vec3 = Vec3::new(0.6, 0.7, 1.0);
+
+// Fraction of the render distance at which fog starts.
+const FOG_START: f32 = 0.5;
+
+pub struct SkyPlugin;
+
+impl Plugin for SkyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .insert_resource(ClearColor(Color::BLACK))
+            .add_startup_system(spawn_sun)
+            .add_system(advance_time_of_day)
+            .add_systems(
+                (update_sun, update_sky, update_terrain_sky_light).after(advance_time_of_day),
+            )
+            .add_system(update_fog.after(update_sky));
+    }
+}
+
+/// Time of day as a fraction of a day, where 0 is midnight and 0.5 is noon.
+/// Pause the cycle or set the time by changing this resource.
+#[derive(Resource, Debug, Clone)]
+pub struct TimeOfDay {
+    pub time: f32,
+    // Real seconds per day.
+    pub day_length: f32,
+    pub paused: bool,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            time: 0.3,
+            day_length: 600.0,
+            paused: false,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn hour(&self) -> f32 {
+        self.time * 24.0
+    }
+
+    pub fn set_hour(&mut self, hour: f32) {
+        self.time = (hour / 24.0).rem_euclid(1.0);
+    }
+
+    /// Direction towards the sun. The moon is always opposite to it.
+    pub fn sun_direction(&self) -> Vec3 {
+        // The sun rises at 6:00 along +X and sets at 18:00 along -X.
+        let angle = (self.time - 0.25) * TAU;
+        let (sin, cos) = angle.sin_cos();
+
+        Vec3::new(cos, sin * SUN_TILT.cos(), sin * SUN_TILT.sin())
+    }
+
+    /// How much of the day's light there is, from 0 at night to 1 during the
+    /// day, fading while the sun is close to the horizon.
+    pub fn daylight(&self) -> f32 {
+        let elevation = self.sun_direction().y;
+        let t = ((elevation + 0.1) / 0.3).clamp(0.0, 1.0);
+
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Brightness of voxel sky light, which never quite reaches darkness.
+    pub fn sky_light(&self) -> f32 {
+        NIGHT_SKY_LIGHT + (1.0 - NIGHT_SKY_LIGHT) * self.daylight()
+    }
+
+    // How close the sun is to the horizon, for the colors of sunrise and
+    // sunset.
+    fn twilight(&self) -> f32 {
+        (1.0 - self.sun_direction().y.abs() / 0.3).clamp(0.0, 1.0)
+    }
+}
+
+/// The directional light of the sun during the day and of the moon at night.
+#[derive(Component)]
+pub struct Sun;
+
+fn spawn_sun(mut commands: Commands) {
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                shadows_enabled: true,
+                ..default()
+            },
+            ..default()
+        },
+        Sun,
+    ));
+}
+
+fn advance_time_of_day(mut time_of_day: ResMut<TimeOfDay>, time: Res<Time>) {
+    if time_of_day.paused || time_of_day.day_length <= 0.0 {
+        return;
+    }
+
+    let day_length = time_of_day.day_length;
+    time_of_day.time = (time_of_day.time + time.delta_seconds() / day_length).rem_euclid(1.0);
+}
+
+fn update_sun(
+    time_of_day: Res<TimeOfDay>,
+    mut suns: Query<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+) {
+    if !time_of_day.is_changed() {
+        return;
+    }
+
+    let sun_direction = time_of_day.sun_direction();
+    let daylight = time_of_day.daylight();
+
+    // The light switches over to the moon once the sun has set.
+    let (direction, illuminance, color) = if sun_direction.y > 0.0 {
+        let color = Vec3::ONE.lerp(SUNSET_COLOR, time_of_day.twilight());
+        (sun_direction, SUN_ILLUMINANCE * daylight, color)
+    } else {
+        (
+            -sun_direction,
+            MOON_ILLUMINANCE * (1.0 - daylight),
+            MOON_COLOR,
+        )
+    };
+
+    for (mut light, mut transform) in &mut suns {
+        light.illuminance = illuminance;
+        light.color = Color::rgb(color.x, color.y, color.z);
+        *transform = Transform::from_translation(direction).looking_at(Vec3::ZERO, Vec3::Y);
+    }
+}
+
+fn update_sky(
+    time_of_day: Res<TimeOfDay>,
+    mut clear_color: ResMut<ClearColor>,
+    mut ambient_light: ResMut<AmbientLight>,
+) {
+    if !time_of_day.is_changed() {
+        return;
+    }
+
+    let daylight = time_of_day.daylight();
+    let sky_color = NIGHT_SKY_COLOR
+        .lerp(DAY_SKY_COLOR, daylight)
+        .lerp(SUNSET_COLOR, time_of_day.twilight() * 0.5);
+
+    let sky_color = Color::rgb(sky_color.x, sky_color.y, sky_color.z);
+    clear_color.0 = sky_color;
+    ambient_light.color = sky_color;
+    ambient_light.brightness = 0.05 + 0.25 * daylight;
+}
+
+// Sky light baked into the chunk meshes is scaled by the time of day in the
+// terrain shader, so changing it doesn't require meshing chunks again.
+fn update_terrain_sky_light(
+    time_of_day: Res<TimeOfDay>,
+    terrain_materials: Option<ResMut<TerrainMaterials>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+) {
+    let Some(mut terrain_materials) = terrain_materials else {
+        return;
+    };
+
+    if !time_of_day.is_changed() && !terrain_materials.is_added() {
+        return;
+    }
+
+    let sky_light = time_of_day.sky_light();
+    terrain_materials.update(&mut materials, |material| material.sky_light = sky_light);
+}
+
+// Fog thickens towards the edge of the render distance, in the color of the
+// sky, so chunks streaming in at the edge emerge from it instead of popping
+// into view.
+fn update_fog(
+    mut commands: Commands,
+    render_distance: Res<RenderDistance>,
+    clear_color: Res<ClearColor>,
+    mut cameras: Query<(Entity, Option<&mut FogSettings>), With<Camera3d>>,
+) {
+    // The last ring of chunks is hidden entirely, as it's only meshed once
+    // the ring beyond it has loaded.
+    let end = ((render_distance.load - 1) * CHUNK_SIZE) as f32;
+    let fog = FogSettings {
+        color: clear_color.0,
+        falloff: FogFalloff::Linear {
+            start: end * FOG_START,
+            end,
+        },
+        ..default()
+    };
+
+    for (id, fog_settings) in &mut cameras {
+        match fog_settings {
+            Some(mut fog_settings) => *fog_settings = fog.clone(),
+            None => {
+                commands.entity(id).insert(fog.clone());
+            }
+        }
+    }
+}
@@ -8,6 +8,16 @@ use noise::{NoiseFn, Simplex};
 pub struct Voxel {
     pub is_active: bool,
     pub block_type: BlockType,
+    // Light levels from 0 to `light::MAX_LIGHT`, maintained by the light module.
+    pub sky_light: u8,
+    pub block_light: u8,
+}
+
+impl Voxel {
+    /// Whether the voxel blocks light and hides what's behind it.
+    pub fn is_opaque(&self) -> bool {
+        self.is_active && self.block_type.render_layer() == RenderLayer::Opaque
+    }
 }
 
 impl Default for Voxel {
@@ -15,6 +25,8 @@ impl Default for Voxel {
         Self {
             is_active: false,
             block_type: BlockType::Default,
+            sky_light: 0,
+            block_light: 0,
         }
     }
 }
@@ -23,6 +35,62 @@ impl Default for Voxel {
 pub enum BlockType {
     Default,
     Grass,
+    Rock,
+    Sand,
+    Lamp,
+    Leaves,
+    Water,
+    Glass,
+}
+
+/// Group of blocks meshed and drawn together, as each is rendered with its
+/// own alpha mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RenderLayer {
+    #[default]
+    Opaque,
+    /// Alpha-tested blocks, such as leaves.
+    Foliage,
+    /// Blended blocks, such as water and glass, drawn after everything else.
+    Translucent,
+}
+
+impl RenderLayer {
+    pub const ALL: [RenderLayer; 3] = [
+        RenderLayer::Opaque,
+        RenderLayer::Foliage,
+        RenderLayer::Translucent,
+    ];
+}
+
+impl BlockType {
+    /// Layer of the terrain texture used by this block.
+    pub fn texture_layer(&self) -> usize {
+        match self {
+            // Lamps, leaves, water and glass don't have textures of their
+            // own yet.
+            BlockType::Default | BlockType::Water | BlockType::Glass => 0,
+            BlockType::Grass | BlockType::Leaves => 1,
+            BlockType::Rock | BlockType::Lamp => 2,
+            BlockType::Sand => 3,
+        }
+    }
+
+    pub fn render_layer(&self) -> RenderLayer {
+        match self {
+            BlockType::Leaves => RenderLayer::Foliage,
+            BlockType::Water | BlockType::Glass => RenderLayer::Translucent,
+            _ => RenderLayer::Opaque,
+        }
+    }
+
+    /// Block light level emitted by this block.
+    pub fn light_emission(&self) -> u8 {
+        match self {
+            BlockType::Lamp => 14,
+            _ => 0,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -50,14 +118,19 @@ impl VoxelData {
     }
 
     // TODO: Landscape generation
-    pub fn generate_height_map(chunk_position: IVec3) -> Self {
+    // At a coarser level of detail only every `1 << lod`th voxel is sampled,
+    // keyed by its position on the coarse grid.
+    pub fn generate_height_map(chunk_position: IVec3, lod: u32) -> Self {
         let mut voxels = HashMap::new();
         let simplex = Simplex::new(42);
-
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                for z in 0..CHUNK_SIZE {
-                    let relative_voxel_position = IVec3::new(x, y, z);
+        let size = CHUNK_SIZE >> lod;
+        let scale = 1 << lod;
+
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let voxel_position = IVec3::new(x, y, z);
+                    let relative_voxel_position = voxel_position * scale;
                     let global_voxel_position =
                         relative_voxel_position + chunk_position * CHUNK_SIZE;
 
@@ -69,18 +142,28 @@ impl VoxelData {
 
                     let elevation = n.powf(0.44);
 
-                    let height = y as f64 / CHUNK_SIZE as f64;
+                    let height = relative_voxel_position.y as f64 / CHUNK_SIZE as f64;
+
+                    // Low ground is sand and peaks are bare rock.
+                    let block_type = if elevation < 0.6 {
+                        BlockType::Sand
+                    } else if elevation > 0.85 {
+                        BlockType::Rock
+                    } else {
+                        BlockType::Grass
+                    };
 
                     if height < elevation {
                         voxels.insert(
-                            relative_voxel_position,
+                            voxel_position,
                             Voxel {
                                 is_active: true,
-                                block_type: BlockType::Grass,
+                                block_type,
+                                ..default()
                             },
                         );
                     } else {
-                        voxels.insert(relative_voxel_position, Voxel::default());
+                        voxels.insert(voxel_position, Voxel::default());
                     }
                 }
             }
@@ -106,6 +189,7 @@ impl VoxelData {
                             Voxel {
                                 is_active: true,
                                 block_type: BlockType::Grass,
+                                ..default()
                             },
                         );
                     } else {
@@ -0,0 +1,164 @@
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy::utils::{Duration, Instant};
+use std::collections::HashSet;
+use voxel_engine::chunk::CHUNK_SIZE;
+use voxel_engine::chunk_manager::plugin::{
+    ChunkEntityMap, ChunkFade, ChunkLayer, ChunkLoader, ChunkPosition, ChunkStreamingPlugin,
+};
+use voxel_engine::chunk_manager::{ChunkManager, ChunkState, RenderDistance};
+use voxel_engine::material::{TerrainMaterial, TerrainMaterials, CHUNK_FADE_DURATION};
+
+const RADIUS: i32 = 3;
+// Time advances by a fixed step every frame, so fades take the same number of
+// frames however fast the machine is.
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// Frames to wait for chunks being generated and meshed in the background.
+const MAX_SETTLE_FRAMES: usize = 100_000;
+
+struct TestApp {
+    app: App,
+    start: Instant,
+    frame: u32,
+}
+
+impl TestApp {
+    fn new() -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_plugin(TransformPlugin)
+            .add_asset::<Mesh>()
+            .add_asset::<TerrainMaterial>()
+            .insert_resource(TerrainMaterials::new(TerrainMaterial::new(
+                Handle::default(),
+            )))
+            .add_plugin(ChunkStreamingPlugin);
+
+        Self {
+            app,
+            start: Instant::now(),
+            frame: 0,
+        }
+    }
+
+    fn update(&mut self) {
+        self.frame += 1;
+        let instant = self.start + FRAME_TIME * self.frame;
+        self.app
+            .insert_resource(TimeUpdateStrategy::ManualInstant(instant));
+        self.app.update();
+    }
+
+    // Whether every chunk in range has been generated and meshed, and every
+    // spawned chunk has faded in.
+    fn is_settled(&mut self) -> bool {
+        let pipeline_idle = self
+            .app
+            .world
+            .resource::<ChunkManager>()
+            .chunk_states()
+            .all(|(_, state)| {
+                !matches!(
+                    state,
+                    ChunkState::Queued | ChunkState::Generating | ChunkState::Meshing
+                )
+            });
+        let fading = self
+            .app
+            .world
+            .query::<&ChunkFade>()
+            .iter(&self.app.world)
+            .len();
+
+        pipeline_idle && fading == 0
+    }
+}
+
+#[test]
+fn asset_counts_stay_bounded_while_walking() {
+    let mut test_app = TestApp::new();
+    let render_distance = *test_app.app.world.resource::<RenderDistance>();
+    let loader = test_app
+        .app
+        .world
+        .spawn((
+            TransformBundle::default(),
+            ChunkLoader {
+                radius: Some(RADIUS),
+            },
+        ))
+        .id();
+
+    // Every chunk the loader keeps loaded has at most one mesh.
+    let unload_distance = RADIUS + render_distance.unload - render_distance.load;
+    let max_meshes = ((2 * unload_distance + 1) * (2 * unload_distance + 1)) as usize;
+
+    let mut peak_meshes = 0;
+
+    for step in 0..24 {
+        let mut transform = test_app.app.world.get_mut::<Transform>(loader).unwrap();
+        transform.translation.x = (step * CHUNK_SIZE) as f32;
+
+        for _ in 0..20 {
+            test_app.update();
+
+            let meshes = test_app.app.world.resource::<Assets<Mesh>>().len();
+            assert!(meshes <= max_meshes, "{meshes} meshes alive at step {step}");
+            peak_meshes = peak_meshes.max(meshes);
+        }
+    }
+
+    assert!(peak_meshes > 0, "no chunks were spawned");
+
+    let mut frames = 0;
+    while !test_app.is_settled() {
+        assert!(frames < MAX_SETTLE_FRAMES, "chunks didn't settle");
+        test_app.update();
+        frames += 1;
+    }
+
+    // Materials of fade groups are dropped shortly after their chunks have
+    // faded in.
+    let fade_frames = (CHUNK_FADE_DURATION / FRAME_TIME.as_secs_f32()).ceil() as usize;
+    for _ in 0..fade_frames {
+        test_app.update();
+    }
+
+    // Once every chunk has faded in, only one fade group is left: one
+    // material per render layer in use.
+    let layers = test_app
+        .app
+        .world
+        .query::<&ChunkLayer>()
+        .iter(&test_app.app.world)
+        .map(|layer| layer.0)
+        .collect::<HashSet<_>>();
+    let fade_groups = 1;
+    let materials = test_app
+        .app
+        .world
+        .resource::<Assets<TerrainMaterial>>()
+        .len();
+    assert!(!layers.is_empty(), "no chunk meshes are left");
+    assert_eq!(materials, layers.len() * fade_groups);
+
+    // Each chunk has a single entity, which the entity map points to.
+    let chunks = test_app
+        .app
+        .world
+        .query::<(Entity, &ChunkPosition)>()
+        .iter(&test_app.app.world)
+        .map(|(id, chunk_position)| (chunk_position.0, id))
+        .collect::<Vec<_>>();
+    let chunk_entity_map = test_app.app.world.resource::<ChunkEntityMap>();
+    for (chunk_position, id) in &chunks {
+        assert_eq!(chunk_entity_map.get(*chunk_position), Some(*id));
+    }
+    let positions = chunks.iter().map(|(pos, _)| *pos).collect::<HashSet<_>>();
+    assert_eq!(
+        positions.len(),
+        chunks.len(),
+        "chunks spawned more than once"
+    );
+}